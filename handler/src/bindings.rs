@@ -0,0 +1,228 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration, process::Stdio};
+
+use aho_corasick::AhoCorasick;
+use anyhow::{Result, Context};
+use lazy_static::lazy_static;
+use log::*;
+use serde::{Serialize, Deserialize};
+
+use crate::{config::{Config, Raise}, communication::Action, messages::MessageSender, media::Metadata, clock::SharedClock};
+
+lazy_static!(
+    static ref PLACEHOLDERS: AhoCorasick = AhoCorasick::new(
+        ["{title}", "{album}", "{artist}", "{position}", "{duration}"]
+    ).expect("parse to work");
+);
+
+/// Media control events that can be bound to a chain of [`Binding`]s
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Event {
+    Play,
+    Pause,
+    Toggle,
+    Next,
+    Previous,
+    Stop,
+    SeekForward,
+    SeekBackward,
+    Raise,
+    Quit,
+}
+
+/// Something the handler does when an event is recieved
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub enum Binding {
+    /// Runs a MusicBee command line command, like `/Next`
+    Command(String),
+    /// Sends an action over to the plugin
+    Action(Action),
+    /// Runs a command with `sh -c`
+    ///
+    /// `{title}`, `{album}`, `{artist}`, `{position}` and `{duration}` are replaced with the
+    /// current track's, already quoted
+    Shell(String),
+    /// Seeks forward by the amount the client asked for, or `media_controls.seek_amount`
+    SeekForward,
+    /// Seeks backward by the amount the client asked for, or `media_controls.seek_amount`
+    SeekBackward,
+    /// Brings MusicBee to the front with `media_controls.raise`
+    Raise,
+    /// Closes MusicBee, if `media_controls.quit` is enabled
+    Quit,
+    /// Runs `then` if the track is past `position`, otherwise runs `otherwise`
+    IfPositionOver {
+        position: Duration,
+        then: Vec<Binding>,
+        #[serde(default)]
+        otherwise: Vec<Binding>,
+    },
+}
+
+/// Bindings set in the config, events that aren't set use [`default_bindings`]
+pub type Bindings = BTreeMap<Event, Vec<Binding>>;
+
+/// What each event does when it isn't bound
+pub fn default_bindings(event: Event) -> Vec<Binding> {
+    let binding = match event {
+        Event::Play | Event::Pause | Event::Toggle => Binding::Command("/PlayPause".to_owned()),
+        Event::Next => Binding::Command("/Next".to_owned()),
+        Event::Previous => Binding::Command("/Previous".to_owned()),
+        Event::Stop => Binding::Command("/Stop".to_owned()),
+        Event::SeekForward => Binding::SeekForward,
+        Event::SeekBackward => Binding::SeekBackward,
+        Event::Raise => Binding::Raise,
+        Event::Quit => Binding::Quit,
+    };
+    vec![binding]
+}
+
+/// The track that's currently playing, for bindings that depend on it
+pub struct Track {
+    metadata: Option<Arc<Metadata>>,
+    clock: SharedClock,
+}
+
+impl Track {
+    pub const fn new(clock: SharedClock) -> Self {
+        Self { metadata: None, clock }
+    }
+
+    pub fn set_metadata(&mut self, metadata: Arc<Metadata>) {
+        self.metadata = Some(metadata);
+    }
+
+    pub fn position(&self) -> Duration {
+        self.clock.position()
+    }
+
+    fn placeholder(&self, key: &str) -> String {
+        let metadata = self.metadata.as_deref();
+        match key {
+            "{title}" => metadata.and_then(|m| m.title.clone()).unwrap_or_default(),
+            "{album}" => metadata.and_then(|m| m.album.clone()).unwrap_or_default(),
+            "{artist}" => metadata.and_then(|m| m.artist.clone()).unwrap_or_default(),
+            "{position}" => self.position().as_secs().to_string(),
+            "{duration}" => metadata.and_then(|m| m.duration)
+                .map(|d| d.as_secs().to_string())
+                .unwrap_or_default(),
+            _ => panic!("tried to get the placeholder for {key}, but it has no replacement"),
+        }
+    }
+}
+
+/// What the bindings of an event can use
+struct Scope<'a> {
+    track: &'a Track,
+    sender: &'a MessageSender,
+    config: &'a Config,
+    seek: Duration,
+}
+
+/// Runs every binding of an event in order
+///
+/// `seek` is how far a seek event asked to seek
+pub fn run(event: Event, seek: Option<Duration>, track: &Track, sender: &MessageSender, config: &Config) -> Result<()> {
+    let scope = Scope {
+        track, sender, config,
+        seek: seek.unwrap_or(config.media_controls.seek_amount),
+    };
+    match config.media_controls.bindings.get(&event) {
+        Some(bindings) => run_all(bindings, &scope),
+        None => run_all(&default_bindings(event), &scope),
+    }
+}
+
+fn run_all(bindings: &[Binding], scope: &Scope) -> Result<()> {
+    for binding in bindings {
+        run_binding(binding, scope)?;
+    }
+    Ok(())
+}
+
+fn run_binding(binding: &Binding, scope: &Scope) -> Result<()> {
+    let Scope { track, sender, config, seek: amount } = *scope;
+    trace!("running binding: {binding:?}");
+    match binding {
+        Binding::Command(command) => config.run_simple_command(command)
+            .with_context(|| format!("failed to run {command}"))?,
+        Binding::Action(action) => sender.action(action.clone()),
+        Binding::Shell(command) => shell(command, track)?,
+        Binding::SeekForward => sender.action(seek(amount, true)?),
+        Binding::SeekBackward => sender.action(seek(amount, false)?),
+        Binding::Raise => match &config.media_controls.raise {
+            Some(raise) => raise_window(raise, sender)?,
+            None => warn!("ignoring raise, media_controls.raise isn't set"),
+        },
+        Binding::Quit => if config.media_controls.quit {
+            sender.action(Action::Quit);
+        } else {
+            warn!("ignoring quit, media_controls.quit isn't enabled");
+        },
+        Binding::IfPositionOver { position, then, otherwise } => {
+            let bindings = if track.position() > *position { then } else { otherwise };
+            run_all(bindings, scope)?;
+        },
+    }
+    Ok(())
+}
+
+/// Creates a seek action in either direction
+fn seek(duration: Duration, forward: bool) -> Result<Action> {
+    let duration: i32 = duration.as_millis().try_into()
+        .context("failed to convert the seek duration into an i32")?;
+
+    let milis = if forward { duration } else { -duration };
+    Ok(Action::Seek { milis })
+}
+
+fn shell(command: &str, track: &Track) -> Result<()> {
+    let mut replaced = String::new();
+    PLACEHOLDERS.replace_all_with(command, &mut replaced, |_, key, dst| {
+        dst.push_str(&quote(&track.placeholder(key))); true
+    });
+
+    trace!("running shell command: {replaced}");
+
+    // the child is reaped by tokio, so it doesn't have to be waited on
+    tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(&replaced)
+        .stdin(Stdio::null())
+        .spawn()
+        .with_context(|| format!("failed to run `{replaced}`"))?;
+    Ok(())
+}
+
+/// Quotes a string so that the shell takes it literally
+fn quote(string: &str) -> String {
+    format!("'{}'", string.replace('\'', r"'\''"))
+}
+
+fn raise_window(raise: &Raise, sender: &MessageSender) -> Result<()> {
+    let (program, args): (_, &[&str]) = match raise {
+        Raise::Plugin => {
+            sender.action(Action::Raise);
+            return Ok(());
+        },
+        Raise::Wmctrl { class } => ("wmctrl", &["-x", "-a", class]),
+        Raise::Xdotool { class } => ("xdotool", &["search", "--class", class, "windowactivate"]),
+    };
+
+    trace!("raising window: {program} {}", args.join(" "));
+
+    let mut child = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .spawn()
+        .with_context(|| format!("failed to run {program}"))?;
+
+    // waiting here would hold up every other media control event
+    tokio::spawn(async move {
+        match child.wait().await {
+            Ok(status) if !status.success() => warn!("{program} couldn't find the window to raise ({status})"),
+            Ok(_) => (),
+            Err(err) => warn!("failed to wait for {program}: {err}"),
+        }
+    });
+    Ok(())
+}
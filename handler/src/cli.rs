@@ -2,7 +2,7 @@ use std::path::PathBuf;
 
 use clap::{Parser, Subcommand, ArgAction, Args};
 
-use crate::config;
+use crate::{config, communication::{Switch, ReplayGainMode}};
 
 // TODO: run --replace or simply just replace
 
@@ -13,13 +13,27 @@ pub struct Cli {
     #[arg(short, long, value_name = "FILE", default_value_os_t = default_config_path())]
     pub config_path: PathBuf,
 
+    /// Runs or controls a named instance, which has its own config and communication directory
+    #[arg(short, long, global = true, value_parser = parse_instance)]
+    pub instance: Option<String>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
 
 impl Cli {
     pub fn config_file(&self) -> PathBuf {
-        self.config_path.join(config::CONFIG_FILE)
+        self.config_path.join(config::config_file_name(self.instance.as_deref()))
+    }
+}
+
+/// Instance names end up in file names and bus names, so they're kept simple
+fn parse_instance(name: &str) -> Result<String, String> {
+    let valid = !name.is_empty() && name.chars().all(|char| char.is_ascii_alphanumeric() || char == '_' || char == '-');
+    if valid {
+        Ok(name.to_owned())
+    } else {
+        Err("instance names can only have letters, numbers, `_` and `-`".to_owned())
     }
 }
 
@@ -50,6 +64,201 @@ pub enum Commands {
         #[arg(short, long)]
         open: bool,
     },
+    /// Check the setup for common problems
+    Doctor,
+    /// Control MusicBee
+    Ctl {
+        #[command(subcommand)]
+        control: Control,
+    },
+    /// Show or change the now playing list
+    ///
+    /// Without a subcommand, this shows the tracks around the playing one
+    Queue {
+        #[command(subcommand)]
+        command: Option<QueueCommand>,
+    },
+    /// Search the library
+    Search {
+        #[command(flatten)]
+        search: SearchArgs,
+    },
+    /// List or play MusicBee's playlists
+    Playlists {
+        #[command(subcommand)]
+        command: PlaylistsCommand,
+    },
+    /// List podcasts and play their unplayed episodes
+    Podcasts {
+        #[command(subcommand)]
+        command: PodcastsCommand,
+    },
+    /// Show the lyrics of the playing track
+    Lyrics {
+        /// Keep printing each synced line as it's sung
+        #[arg(short, long)]
+        follow: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PodcastsCommand {
+    /// List the subscriptions along with their unplayed episodes
+    List {
+        /// Print the podcasts as json
+        #[arg(short, long)]
+        json: bool,
+    },
+    /// Play one of a podcast's unplayed episodes now
+    Play {
+        /// The title of the podcast
+        podcast: String,
+        /// Which unplayed episode to play, numbered like `podcasts list` shows them
+        #[arg(default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+        episode: u64,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PlaylistsCommand {
+    /// List the names of the playlists
+    List,
+    /// Play a playlist by its name
+    Play {
+        name: String,
+    },
+}
+
+/// Positions in the now playing list start at 1, the same as `queue` shows them
+#[derive(Subcommand)]
+pub enum QueueCommand {
+    /// Play the first file now, and queue the rest after it
+    PlayNow {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Queue the files after the playing track
+    Next {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Queue the files at the end of the now playing list
+    Last {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Remove the track at a position
+    Remove {
+        #[arg(value_parser = clap::value_parser!(u64).range(1..))]
+        position: u64,
+    },
+    /// Move the tracks at the positions to another position
+    Move {
+        #[arg(required = true, value_parser = clap::value_parser!(u64).range(1..))]
+        positions: Vec<u64>,
+        /// Where to move the tracks to
+        #[arg(short, long, value_parser = clap::value_parser!(u64).range(1..))]
+        to: u64,
+    },
+    /// Remove every track from the now playing list
+    Clear,
+}
+
+#[derive(Subcommand)]
+pub enum Control {
+    /// Rate the playing track out of 5 stars, 0 removes the rating
+    Rate {
+        #[arg(value_parser = clap::value_parser!(u8).range(0..=5))]
+        stars: u8,
+    },
+    /// Love the playing track, or unlove it if it's already loved
+    Love,
+    /// Mute or unmute the player
+    Mute {
+        #[arg(value_enum, default_value_t)]
+        switch: Switch,
+    },
+    /// Stop once the playing track ends
+    StopAfterCurrent {
+        #[arg(value_enum, default_value_t)]
+        switch: Switch,
+    },
+    /// Start or end AutoDJ
+    AutoDj {
+        #[arg(value_enum, default_value_t)]
+        switch: Switch,
+    },
+    /// Play the next album
+    NextAlbum,
+    /// Play the previous album
+    PreviousAlbum,
+    /// Queue up random tracks
+    QueueRandom {
+        #[arg(default_value_t = 10)]
+        count: u32,
+    },
+    /// Turn the equaliser on or off
+    Equaliser {
+        #[arg(value_enum, default_value_t)]
+        switch: Switch,
+    },
+    /// Turn the DSP effects on or off
+    Dsp {
+        #[arg(value_enum, default_value_t)]
+        switch: Switch,
+    },
+    /// Turn crossfading on or off
+    Crossfade {
+        #[arg(value_enum, default_value_t)]
+        switch: Switch,
+    },
+    /// Set how ReplayGain evens out the loudness
+    ReplayGain {
+        #[arg(value_enum)]
+        mode: ReplayGainMode,
+    },
+    /// List or switch the output devices
+    Output {
+        #[command(subcommand)]
+        output: Output,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum Output {
+    /// List the devices, marking the active one with a *
+    List,
+    /// Play through another device
+    Set {
+        name: String,
+    },
+}
+
+#[derive(Args)]
+pub struct SearchArgs {
+    /// Words that each have to be in the title, artist or album
+    pub text: Vec<String>,
+    /// Only find tracks with an artist containing this
+    #[arg(long)]
+    pub artist: Option<String>,
+    /// Only find tracks with an album containing this
+    #[arg(long)]
+    pub album: Option<String>,
+    /// Only find tracks with a title containing this
+    #[arg(long)]
+    pub title: Option<String>,
+    /// The most tracks to find, 0 finds all of them
+    #[arg(short, long, default_value_t = 100)]
+    pub limit: u32,
+    /// Print the results as json
+    #[arg(short, long)]
+    pub json: bool,
+    /// Play the first result now, and queue the rest after it
+    #[arg(short, long, conflicts_with = "queue")]
+    pub play: bool,
+    /// Queue the results at the end of the now playing list
+    #[arg(short, long)]
+    pub queue: bool,
 }
 
 #[derive(Args)]
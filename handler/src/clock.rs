@@ -0,0 +1,138 @@
+use std::{sync::{Arc, Mutex, MutexGuard}, time::Duration};
+
+use tokio::time::Instant;
+
+use crate::media::{MediaPlayback, MediaPosition};
+
+/// How far the plugin's position can be from the clock before it counts as a seek
+pub const SEEK_THRESHOLD: Duration = Duration::from_millis(1000);
+
+/// Keeps track of the position between the plugin's updates
+///
+/// The plugin only sends the position when the playback changes, so the clock
+/// remembers where it was and when, and counts up from there while playing
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Clock {
+    anchor: Duration,
+    /// when the anchor was set, only while playing
+    since: Option<Instant>,
+    /// whether there's a track to predict the position of
+    synced: bool,
+}
+
+impl Clock {
+    /// The current position of the track
+    pub fn position(&self) -> Duration {
+        match self.since {
+            Some(since) => self.anchor + since.elapsed(),
+            None => self.anchor,
+        }
+    }
+
+    pub const fn playing(&self) -> bool { self.since.is_some() }
+
+    /// Starts counting from the beginning again, for when the track changes
+    pub fn restart(&mut self) {
+        self.anchor = Duration::ZERO;
+        if self.since.is_some() {
+            self.since = Some(Instant::now());
+        }
+    }
+
+    /// Moves the clock to the new playback
+    ///
+    /// Returns the new position if it jumped away from where the clock thought it would be
+    pub fn update(&mut self, playback: &MediaPlayback) -> Option<Duration> {
+        let predicted = self.position();
+        let was_synced = self.synced;
+
+        let (position, playing) = match playback {
+            MediaPlayback::Stopped => {
+                *self = Self::default();
+                return None;
+            },
+            MediaPlayback::Paused { progress } => (progress, false),
+            MediaPlayback::Playing { progress } => (progress, true),
+        };
+        let position = position.map_or(predicted, |MediaPosition(position)| position);
+
+        *self = Self {
+            anchor: position,
+            since: playing.then(Instant::now),
+            synced: true,
+        };
+
+        let jumped = position.abs_diff(predicted) > SEEK_THRESHOLD;
+        (jumped && was_synced).then_some(position)
+    }
+}
+
+/// A [`Clock`] shared between everything that needs the position
+#[derive(Clone, Default)]
+pub struct SharedClock(Arc<Mutex<Clock>>);
+
+impl SharedClock {
+    pub fn lock(&self) -> MutexGuard<'_, Clock> {
+        crate::lock_unpoisoned(&self.0)
+    }
+
+    pub fn position(&self) -> Duration { self.lock().position() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paused(millis: u64) -> MediaPlayback {
+        MediaPlayback::Paused { progress: Some(MediaPosition(Duration::from_millis(millis))) }
+    }
+
+    fn playing(millis: u64) -> MediaPlayback {
+        MediaPlayback::Playing { progress: Some(MediaPosition(Duration::from_millis(millis))) }
+    }
+
+    #[test]
+    fn counting() {
+        let mut clock = Clock::default();
+        clock.update(&paused(5_000));
+        assert_eq!(clock.position(), Duration::from_millis(5_000));
+        assert!(!clock.playing());
+
+        clock.update(&playing(5_000));
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(clock.playing());
+        assert!(clock.position() >= Duration::from_millis(5_020));
+
+        // the plugin doesn't always know the position, so the clock keeps its own
+        clock.update(&MediaPlayback::Paused { progress: None });
+        assert!(clock.position() >= Duration::from_millis(5_020));
+    }
+
+    #[test]
+    fn seeks() {
+        let mut clock = Clock::default();
+        // there's nothing to jump away from yet
+        assert_eq!(clock.update(&paused(60_000)), None);
+
+        assert_eq!(clock.update(&paused(60_000 + 900)), None);
+        assert_eq!(clock.update(&paused(60_000 - 100)), None);
+        assert_eq!(clock.update(&paused(70_000)), Some(Duration::from_millis(70_000)));
+        assert_eq!(clock.update(&playing(30_000)), Some(Duration::from_millis(30_000)));
+        assert_eq!(clock.update(&paused(30_500)), None);
+    }
+
+    #[test]
+    fn stopping_and_restarting() {
+        let mut clock = Clock::default();
+        clock.update(&paused(30_000));
+        assert_eq!(clock.update(&MediaPlayback::Stopped), None);
+        assert_eq!(clock.position(), Duration::ZERO);
+        // starting again after a stop isn't a seek
+        assert_eq!(clock.update(&paused(90_000)), None);
+
+        // a new track starts from the beginning, so its first update isn't a seek either
+        clock.restart();
+        assert_eq!(clock.position(), Duration::ZERO);
+        assert_eq!(clock.update(&paused(500)), None);
+    }
+}
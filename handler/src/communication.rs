@@ -1,10 +1,14 @@
-use std::{time::Duration, fmt::Display, io};
+use std::{time::Duration, fmt::Display, str::FromStr};
 
+use clap::ValueEnum;
 use log::*;
+use serde::{Serialize, Deserialize};
 
-use crate::{config::Config, filesystem::ACTION_FILE};
+use anyhow::{Result, Context};
 
-#[allow(dead_code)]
+use crate::{config::{Config, Transport}, filesystem::ACTION_FILE, transport::socket};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum RepeatMode {
     None,
     All,
@@ -23,13 +27,154 @@ impl Display for RepeatMode {
     }
 }
 
-#[allow(dead_code)]
+impl FromStr for RepeatMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "all" => Ok(Self::All),
+            "one" => Ok(Self::One),
+            _ => Err(s.to_owned()),
+        }
+    }
+}
+
+/// How MusicBee evens out the loudness of tracks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default, ValueEnum)]
+pub enum ReplayGainMode {
+    #[default]
+    Off,
+    Track,
+    Album,
+    Smart,
+}
+
+impl Display for ReplayGainMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            Self::Off => "off",
+            Self::Track => "track",
+            Self::Album => "album",
+            Self::Smart => "smart",
+        };
+
+        write!(f, "{string}")
+    }
+}
+
+impl FromStr for ReplayGainMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            "track" => Ok(Self::Track),
+            "album" => Ok(Self::Album),
+            "smart" => Ok(Self::Smart),
+            _ => Err(s.to_owned()),
+        }
+    }
+}
+
+/// What to look for in the library
+///
+/// Every field that's set has to match
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchQuery {
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    /// Words that each have to be in the title, artist or album
+    pub text: Option<String>,
+    /// The most tracks to find, 0 finds all of them
+    pub limit: u32,
+}
+
+impl Display for SearchQuery {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let fields = [
+            ("artist", &self.artist),
+            ("album", &self.album),
+            ("title", &self.title),
+            ("text", &self.text),
+        ];
+
+        // the fields are seperated by tabs, so they can't have any
+        for (name, value) in fields {
+            if let Some(value) = value {
+                write!(f, "\t{name}\t{}", value.replace(['\t', '\n', '\r'], " "))?;
+            }
+        }
+        write!(f, "\tlimit\t{}", self.limit)
+    }
+}
+
+/// Turns a player setting on or off
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default, ValueEnum)]
+pub enum Switch {
+    On,
+    Off,
+    #[default]
+    Toggle,
+}
+
+impl Display for Switch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            Self::On => "on",
+            Self::Off => "off",
+            Self::Toggle => "toggle",
+        };
+
+        write!(f, "{string}")
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Action {
     Shuffle(bool),
     Repeat(RepeatMode),
     Seek { milis: i32 },
     Position(Duration),
     Volume(f64),
+    Raise,
+    Quit,
+    /// Rates the playing track out of 5 stars, 0 removes the rating
+    Rate(u8),
+    ToggleLove,
+    Mute(Switch),
+    StopAfterCurrent(Switch),
+    AutoDj(Switch),
+    NextAlbum,
+    PreviousAlbum,
+    /// Queues up a number of random tracks
+    QueueRandom(u32),
+    Equaliser(Switch),
+    Dsp(Switch),
+    Crossfade(Switch),
+    ReplayGain(ReplayGainMode),
+    /// Plays the track at this index of the now playing list
+    PlayIndex(usize),
+    /// Plays the first file now, and queues the rest up after it
+    ///
+    /// The files are paths that MusicBee can open
+    PlayNow(Vec<String>),
+    QueueNext(Vec<String>),
+    QueueLast(Vec<String>),
+    /// Removes the track at this index of the now playing list
+    RemoveFromQueue(usize),
+    /// Moves the tracks at these indices of the now playing list to another index
+    MoveInQueue { from: Vec<usize>, to: usize },
+    ClearQueue,
+    /// Plays the playlist with this url
+    PlayPlaylist(String),
+    /// Searches the library, writing the results along with the id
+    Search { id: u32, query: SearchQuery },
+    /// Lists the podcast subscriptions and their unplayed episodes, writing them along with the id
+    Podcasts { id: u32 },
+    /// Plays through the output device with this name
+    OutputDevice(String),
 }
 
 impl Display for Action {
@@ -41,22 +186,64 @@ impl Display for Action {
             Self::Position(val) => write!(f, "position {}", val.as_millis()),
             #[allow(clippy::cast_possible_truncation)]
             Self::Volume(val) => write!(f, "volume {}", (val * 100.0) as i32),
+            Self::Raise => write!(f, "raise"),
+            Self::Quit => write!(f, "quit"),
+            Self::Rate(stars) => write!(f, "rate {stars}"),
+            Self::ToggleLove => write!(f, "love"),
+            Self::Mute(switch) => write!(f, "mute {switch}"),
+            Self::StopAfterCurrent(switch) => write!(f, "stop_after_current {switch}"),
+            Self::AutoDj(switch) => write!(f, "auto_dj {switch}"),
+            Self::NextAlbum => write!(f, "next_album"),
+            Self::PreviousAlbum => write!(f, "previous_album"),
+            Self::QueueRandom(count) => write!(f, "queue_random {count}"),
+            Self::Equaliser(switch) => write!(f, "equaliser {switch}"),
+            Self::Dsp(switch) => write!(f, "dsp {switch}"),
+            Self::Crossfade(switch) => write!(f, "crossfade {switch}"),
+            Self::ReplayGain(mode) => write!(f, "replay_gain {mode}"),
+            Self::PlayIndex(index) => write!(f, "play_index {index}"),
+            // file names can have spaces, but not tabs
+            Self::PlayNow(files) => write!(f, "play_now {}", files.join("\t")),
+            Self::QueueNext(files) => write!(f, "queue_next {}", files.join("\t")),
+            Self::QueueLast(files) => write!(f, "queue_last {}", files.join("\t")),
+            Self::RemoveFromQueue(index) => write!(f, "queue_remove {index}"),
+            Self::MoveInQueue { from, to } => {
+                write!(f, "queue_move {to}")?;
+                from.iter().try_for_each(|index| write!(f, " {index}"))
+            },
+            Self::ClearQueue => write!(f, "queue_clear"),
+            Self::PlayPlaylist(url) => write!(f, "play_playlist {url}"),
+            Self::Search { id, query } => write!(f, "search {id}{query}"),
+            Self::Podcasts { id } => write!(f, "podcasts {id}"),
+            Self::OutputDevice(name) => write!(f, "output_device {name}"),
         }
     }
 }
 
 impl Action {
-    pub async fn run(&self, config: &Config) -> io::Result<()> {
+    /// Sends the action through the running daemon's socket if there is one,
+    /// otherwise through the action file
+    pub async fn run(&self, config: &Config) -> Result<()> {
+        if let Transport::Socket { port } = config.communication.transport {
+            if socket::forward(self, port, config).await? { return Ok(()) }
+            debug!("the daemon isn't running, falling back to the action file");
+        }
+        self.write(config).await
+    }
+
+    /// Writes the action to the action file, and pokes the plugin to read it
+    pub async fn write(&self, config: &Config) -> Result<()> {
         let action = self.to_string();
         debug!("running action: {action}");
 
-        config.write_comm_file(ACTION_FILE, &action).await?;
+        config.write_comm_file(ACTION_FILE, &action).await
+            .context("failed to write the action file")?;
 
         trace!("notifying musicbee (volume down)");
 
         // HACK: to notify the plugin that an action is ready,
         // the handler runs /VolumeDown
-        config.run_command("/VolumeDown", None)?;
+        config.run_command("/VolumeDown", None)
+            .context("failed to notify MusicBee")?;
         Ok(())
     }
 }
@@ -10,7 +10,7 @@ use tokio::{fs, io};
 
 use log::*;
 
-use crate::listener::rpc;
+use crate::{listener::rpc, bindings::Bindings};
 
 // TODO: accept null for mappings 
 
@@ -27,12 +27,14 @@ fn get_username() -> String {
 
 lazy_static!(
     // searches for multiple things at a time
-    pub static ref REFERENCES: AhoCorasick = AhoCorasick::new(["{home_dir}", "{username}", "{wine_prefix}"]).expect("parse to work");
+    pub static ref REFERENCES: AhoCorasick = AhoCorasick::new(["{home_dir}", "{runtime_dir}", "{username}", "{wine_prefix}"]).expect("parse to work");
 );
 
 fn replace(key: &str, config: &UnresolvedConfig) -> String {
     match key {
         "{home_dir}" => get_home_dir(),
+        // unlike /tmp, the runtime directory can only be accessed by the current user
+        "{runtime_dir}" => dirs::runtime_dir().map_or_else(|| "/tmp".to_owned(), |runtime| runtime.display().to_string()),
         "{username}" => get_username(),
         "{wine_prefix}" => config.commands.wine_prefix.get_recursive(config),
         _ => panic!("tried to get the replacement for {key}, but it has no replacement"),
@@ -85,6 +87,12 @@ impl ReferencedString {
     pub fn get(&self) -> &str {
         &self.referred
     }
+
+    /// A string without any references in it
+    #[cfg(test)]
+    pub fn plain(text: &str) -> Self {
+        Self { template: text.to_owned(), referred: text.to_owned() }
+    }
 }
 
 // resolvers
@@ -108,6 +116,16 @@ impl Commands<UnresolvedReference> {
     }
 }
 
+impl Communication<UnresolvedReference> {
+    pub fn resolve(self, config: &UnresolvedConfig) -> Communication<ReferencedString> {
+        Communication {
+            directory: self.directory.resolve(config),
+            transport: self.transport,
+            watch_mode: self.watch_mode,
+        }
+    }
+}
+
 impl UnresolvedConfig {
     pub fn resolve(self) -> Config {
         // the config has to be cloned to make sure the values don't change while it's being read
@@ -117,9 +135,12 @@ impl UnresolvedConfig {
             temporary_file_mapper: self.temporary_file_mapper.resolve(&cloned),
             commands: self.commands.resolve(&cloned),
             rpc: self.rpc,
-            communication: self.communication,
+            communication: self.communication.resolve(&cloned),
             detach_on_stop: self.detach_on_stop,
+            detach_grace_period: self.detach_grace_period,
             exit_with_plugin: self.exit_with_plugin,
+            resume_podcasts: self.resume_podcasts,
+            instance: self.instance,
             media_controls: self.media_controls,
         }
     }
@@ -167,6 +188,12 @@ impl Mapping<ReferencedString> {
     pub fn map(&self, string: &str) -> String {
         string.replace(self.from.get(), self.to.get())
     }
+
+    /// Maps a string back, if it starts with what the mapping maps to
+    pub fn unmap(&self, string: &str) -> Option<String> {
+        string.strip_prefix(self.to.get())
+            .map(|rest| format!("{}{rest}", self.from.get()))
+    }
 }
 
 
@@ -211,13 +238,37 @@ impl Commands<ReferencedString> {
 
 /// Info for communication between the handler and the plugin
 #[derive(Clone, Serialize, Deserialize, Debug)]
-pub struct Communication {
-    pub directory: String,
-}
-
-impl Communication {
+pub struct Communication<T> {
+    pub directory: T,
+    #[serde(default)]
+    pub transport: Transport,
+    #[serde(default)]
+    pub watch_mode: WatchMode,
+}
+
+/// How state gets between the handler and the plugin
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub enum Transport {
+    /// Through files in the communication directory
+    #[default]
+    Directory,
+    /// Through a localhost tcp socket that the plugin connects to
+    Socket { port: u16 },
+}
+
+/// How the handler watches the communication directory for changes
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub enum WatchMode {
+    /// Uses inotify, but falls back to polling if inotify doesn't work
+    #[default]
+    Auto,
+    Inotify,
+    Poll { interval: Duration },
+}
+
+impl Communication<ReferencedString> {
     pub fn get_comm_path(&self, name: &str) -> PathBuf {
-        PathBuf::from(format!("{}/{name}", self.directory))
+        PathBuf::from(format!("{}/{name}", self.directory.get()))
     }
 
     pub async fn read_comm_file(&self, name: &str) -> io::Result<String> {
@@ -234,12 +285,73 @@ pub struct MediaControls {
     pub enabled: bool,
     pub seek_amount: Duration,
     pub send_volume: bool,
+    /// How the volume of the media controls maps onto MusicBee's
+    #[serde(default)]
+    pub volume_curve: VolumeCurve,
+    /// How to bring MusicBee to the front, `CanRaise` is only advertised when set
+    #[serde(default)]
+    pub raise: Option<Raise>,
+    /// Whether MusicBee can be closed through the media controls
+    #[serde(default)]
+    pub quit: bool,
+    /// What media control events do, events that aren't set keep their default
+    #[serde(default)]
+    pub bindings: Bindings,
+    /// The dbus address to serve the controls on, defaults to the session bus
+    #[serde(default)]
+    pub bus_address: Option<String>,
+    /// What media widgets call the player, defaults to MusicBee along with the instance
+    #[serde(default)]
+    pub display_name: Option<String>,
+}
+
+/// How the volume of the media controls maps onto MusicBee's
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub enum VolumeCurve {
+    /// The volumes are the same
+    #[default]
+    Linear,
+    /// MusicBee's volume is the cube of the media controls' volume,
+    /// so that sliders sound like they change evenly
+    Cubic,
+}
+
+impl VolumeCurve {
+    /// Converts a volume from the media controls into MusicBee's volume
+    pub fn player_volume(self, volume: f64) -> f64 {
+        match self {
+            Self::Linear => volume,
+            Self::Cubic => volume.powi(3),
+        }
+    }
+
+    /// Converts MusicBee's volume into a volume for the media controls
+    pub fn controls_volume(self, volume: f64) -> f64 {
+        match self {
+            Self::Linear => volume,
+            Self::Cubic => volume.cbrt(),
+        }
+    }
+}
+
+/// Ways to bring MusicBee's window to the front
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub enum Raise {
+    /// Asks the plugin to activate its own window
+    Plugin,
+    /// Runs `wmctrl -x -a {class}`
+    Wmctrl { class: String },
+    /// Runs `xdotool search --class {class} windowactivate`
+    Xdotool { class: String },
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Rpc {
     pub enabled: bool,
     pub service: rpc::Service,
+    /// Show the line being sung instead of the title
+    #[serde(default)]
+    pub lyrics: bool,
 }
 
 pub type Config = Referenced<ReferencedString>;
@@ -249,15 +361,28 @@ type UnresolvedConfig = Referenced<UnresolvedReference>;
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Referenced<T> {
     pub commands: Commands<T>,
-    pub communication: Communication,
+    pub communication: Communication<T>,
     pub media_controls: MediaControls,
     pub rpc: Rpc,
     pub music_file_mapper: Mapping<T>,
     pub temporary_file_mapper: Mapping<T>,
     pub detach_on_stop: bool,
+    /// How long MusicBee has to stay stopped before detaching,
+    /// since it's briefly stopped between tracks
+    #[serde(default = "default_detach_grace_period")]
+    pub detach_grace_period: Duration,
     pub exit_with_plugin: bool,
+    /// Whether podcast episodes pick up where they were left off
+    #[serde(default = "default_resume_podcasts")]
+    pub resume_podcasts: bool,
+    /// The instance that the config belongs to, from `--instance`
+    #[serde(skip)]
+    pub instance: Option<String>,
 }
 
+const fn default_detach_grace_period() -> Duration { Duration::from_secs(2) }
+const fn default_resume_podcasts() -> bool { true }
+
 impl Config {
     pub fn map_filename(&self, name: &str) -> String {
         // we don't like \ here
@@ -271,6 +396,18 @@ impl Config {
         }
     }
 
+    /// Maps a linux path to one that MusicBee can open
+    ///
+    /// Paths outside of the mapped folders go through wine's `Z:` drive
+    pub fn unmap_filename(&self, path: &str) -> String {
+        let name = self.music_file_mapper.unmap(path)
+            .or_else(|| self.temporary_file_mapper.unmap(path))
+            .unwrap_or_else(|| format!("Z:{path}"));
+
+        // windows likes \ more
+        name.replace('/', "\\")
+    }
+
     pub fn run_command(&self, command: &str, arg: Option<String>) -> io::Result<()> {
         self.commands.run_command(command, arg)
     }
@@ -283,6 +420,15 @@ impl Config {
         self.communication.get_comm_path(name)
     }
 
+    /// What media widgets call the player
+    pub fn display_name(&self) -> String {
+        match (&self.media_controls.display_name, &self.instance) {
+            (Some(name), _) => name.clone(),
+            (None, Some(instance)) => format!("MusicBee ({instance})"),
+            (None, None) => "MusicBee".to_owned(),
+        }
+    }
+
     pub async fn read_comm_file(&self, name: &str) -> io::Result<String> {
         self.communication.read_comm_file(name).await
     }
@@ -295,7 +441,13 @@ impl Config {
 
 impl Default for Config {
     fn default() -> Self {
-        UnresolvedConfig {
+        UnresolvedConfig::default().resolve()
+    }
+}
+
+impl Default for UnresolvedConfig {
+    fn default() -> Self {
+        Self {
             communication: Communication::default(),
             commands: Commands::default(),
             media_controls: MediaControls::default(),
@@ -309,9 +461,11 @@ impl Default for Config {
                 to: "{wine_prefix}/drive_c/".into(),
             },
             detach_on_stop: true,
+            detach_grace_period: default_detach_grace_period(),
             exit_with_plugin: true,
+            resume_podcasts: default_resume_podcasts(),
+            instance: None,
         }
-            .resolve()
     }
 }
 
@@ -326,10 +480,12 @@ impl Default for Commands<UnresolvedReference> {
     }
 }
 
-impl Default for Communication {
+impl Default for Communication<UnresolvedReference> {
     fn default() -> Self {
         Self {
-            directory: "/tmp/musicbee-mediakeys".to_string(),
+            directory: "{runtime_dir}/musicbee-mediakeys".into(),
+            transport: Transport::default(),
+            watch_mode: WatchMode::default(),
         }
     }
 }
@@ -340,6 +496,12 @@ impl Default for MediaControls {
             enabled: true,
             seek_amount: Duration::from_secs(5),
             send_volume: true,
+            volume_curve: VolumeCurve::default(),
+            raise: None,
+            quit: false,
+            bindings: Bindings::default(),
+            bus_address: None,
+            display_name: None,
         }
     }
 }
@@ -350,6 +512,7 @@ impl Default for Rpc {
         Self {
             enabled: false,
             service: rpc::Service::Imgur,
+            lyrics: false,
         }
     }
 }
@@ -363,28 +526,61 @@ pub enum GetError {
 
 pub const CONFIG_FILE: &str = "config.ron";
 
-pub fn get_or_save_default(folder: &Path) -> (Config, Option<Error>) {
-    match get(folder) {
+/// The name of the config file, each instance has its own
+pub fn config_file_name(instance: Option<&str>) -> String {
+    match instance {
+        Some(instance) => format!("config-{instance}.ron"),
+        None => CONFIG_FILE.to_owned(),
+    }
+}
+
+pub fn get_or_save_default(folder: &Path, instance: Option<&str>) -> (Config, Option<Error>) {
+    match get(folder, instance) {
         Ok(config) => (config, None),
         Err(err) => (
-            save_default(folder).unwrap_or_default(),
+            save_default(folder, instance).unwrap_or_else(|_| default_for(instance)),
             // don't error if the config isn't found
             (!err.is::<GetError>()).then_some(err)
         )
     }
 }
 
-pub fn get(folder: &Path) -> Result<Config> {
-    let file = folder.join(CONFIG_FILE);
+pub fn get(folder: &Path, instance: Option<&str>) -> Result<Config> {
+    let file = folder.join(config_file_name(instance));
     if !file.exists() { return Err(GetError::NotFound.into()); }
 
     let contents = &std::fs::read_to_string(&file).context("failed to read config")?;
-    ron::from_str::<Config>(contents).context("failed to parse config")
+    let mut config = ron::from_str::<Config>(contents).context("failed to parse config")?;
+    config.instance = instance.map(ToOwned::to_owned);
+    Ok(config)
+}
+
+/// Every instance with a config file in the folder, with None for the main one
+pub fn instances(folder: &Path) -> Vec<Option<String>> {
+    let Ok(entries) = std::fs::read_dir(folder) else { return Vec::new() };
+    entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter_map(|name| match name.as_str() {
+            CONFIG_FILE => Some(None),
+            name => name.strip_prefix("config-")?.strip_suffix(".ron").map(|instance| Some(instance.to_owned())),
+        })
+        .collect()
+}
+
+/// The default config, with a communication directory of the instance's own
+fn default_for(instance: Option<&str>) -> Config {
+    let mut config = UnresolvedConfig::default();
+    if let Some(instance) = instance {
+        config.communication.directory = format!("{}-{instance}", config.communication.directory.template).as_str().into();
+    }
+    let mut config = config.resolve();
+    config.instance = instance.map(ToOwned::to_owned);
+    config
 }
 
-pub fn save_default(folder: &Path) -> Result<Config> {
-    let file = folder.join(CONFIG_FILE);
-    let config = Config::default();
+pub fn save_default(folder: &Path, instance: Option<&str>) -> Result<Config> {
+    let file = folder.join(config_file_name(instance));
+    let config = default_for(instance);
     let serialized = ron::ser::to_string_pretty(&config, PrettyConfig::new())
         .context("failed to serialize default config")?;
 
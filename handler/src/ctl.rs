@@ -0,0 +1,88 @@
+use std::{time::{Duration, SystemTime, UNIX_EPOCH}, process};
+
+use anyhow::{Result, Context, bail};
+use log::*;
+use tokio::time::{self, Instant};
+
+use crate::{cli::{Control, Output}, config::Config, communication::Action, filesystem::{self, OUTPUT_DEVICES_FILE}};
+
+/// How long to wait for the plugin to answer a request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often to check whether the plugin wrote the answer
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Sends the action straight to the plugin, so the daemon doesn't have to be running
+pub fn run(config: &Config, control: &Control) -> Result<()> {
+    let action = match control {
+        Control::Rate { stars } => Action::Rate(*stars),
+        Control::Love => Action::ToggleLove,
+        Control::Mute { switch } => Action::Mute(*switch),
+        Control::StopAfterCurrent { switch } => Action::StopAfterCurrent(*switch),
+        Control::AutoDj { switch } => Action::AutoDj(*switch),
+        Control::NextAlbum => Action::NextAlbum,
+        Control::PreviousAlbum => Action::PreviousAlbum,
+        Control::QueueRandom { count } => Action::QueueRandom(*count),
+        Control::Equaliser { switch } => Action::Equaliser(*switch),
+        Control::Dsp { switch } => Action::Dsp(*switch),
+        Control::Crossfade { switch } => Action::Crossfade(*switch),
+        Control::ReplayGain { mode } => Action::ReplayGain(*mode),
+        Control::Output { output: Output::List } => return list_output_devices(config),
+        Control::Output { output: Output::Set { name } } => Action::OutputDevice(name.clone()),
+    };
+
+    send(config, &action)
+}
+
+/// Sends a single action to the plugin
+pub fn send(config: &Config, action: &Action) -> Result<()> {
+    crate::run_async(async {
+        action.run(config).await.context("failed to send the action to the plugin")
+    })
+}
+
+/// Sends an action that the plugin answers in a file, waiting for the answer
+///
+/// The plugin writes the id it was given along with the answer, so that answers to older
+/// requests are skipped
+pub async fn request<T>(
+    config: &Config,
+    action: impl FnOnce(u32) -> Action,
+    file: &str,
+    parse: impl Fn(&str) -> Result<Option<(u32, T)>>,
+) -> Result<T> {
+    let id = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().subsec_nanos() ^ process::id();
+
+    action(id).run(config).await
+        .context("failed to send the request to the plugin")?;
+
+    let deadline = Instant::now() + REQUEST_TIMEOUT;
+    loop {
+        let contents = config.read_comm_file(file).await
+            .context("failed to read the answer")?;
+
+        // the plugin might still be writing the file
+        match parse(&contents) {
+            Ok(Some((answered, answer))) if answered == id => return Ok(answer),
+            Ok(_) => (),
+            Err(err) => trace!("failed to parse the answer, trying again: {err:?}"),
+        }
+
+        if Instant::now() >= deadline {
+            bail!("the plugin didn't answer in {REQUEST_TIMEOUT:?}, is MusicBee running?");
+        }
+        time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Prints the devices that the plugin last published
+fn list_output_devices(config: &Config) -> Result<()> {
+    let contents = std::fs::read_to_string(config.get_comm_path(OUTPUT_DEVICES_FILE))
+        .context("failed to read the output devices")?;
+    let devices = filesystem::parse_output_devices(&contents);
+
+    for device in &devices.devices {
+        let marker = if devices.active.as_ref() == Some(device) { '*' } else { ' ' };
+        println!("{marker} {device}");
+    }
+    Ok(())
+}
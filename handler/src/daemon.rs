@@ -5,12 +5,15 @@ use anyhow::{Result, Context, bail, Error};
 use log::{error, debug, trace};
 use tokio::task;
 
-use crate::{config::Config, listener::{media_controls::Controls, self, rpc::Rpc, Logger}, filesystem::{self, Filesystem}, tray, messages::Messages, cli::RunConfig, logger};
+use crate::{config::Config, listener::{media_controls::Controls, self, rpc::Rpc, Logger}, transport, tray, messages::Messages, cli::RunConfig, logger};
 
 pub fn pid_file(config: &Config) -> PathBuf {
     crate::project_dirs().and_then(|directories| directories.runtime_dir().map(Path::to_owned))
-        .unwrap_or_else(|| Path::new(&config.communication.directory).to_owned())
-        .join("daemon.pid")
+        .unwrap_or_else(|| Path::new(config.communication.directory.get()).to_owned())
+        .join(match &config.instance {
+            Some(instance) => format!("daemon-{instance}.pid"),
+            None => "daemon.pid".to_owned(),
+        })
 }
 
 pub fn get_pid(config: &Config) -> Result<Option<u32>> {
@@ -108,59 +111,51 @@ async fn create(config: Config, tray: bool) -> Result<()> {
     // setup listeners
     let mut listeners = listener::List::new();
     listeners.add(Logger);
-    listeners.add(Filesystem::new(messages.sender()));
+    // the transport starts talking to the plugin once it starts listening
+    listeners.add(transport::create(messages.sender(), &config));
 
     // media controls
     if config.media_controls.enabled {
-        let controls = Controls::new(messages.sender())
-            .context("failed to initialize the media controls")?;
-        listeners.add(controls);
+        listeners.add(Controls::new(messages.sender()));
+    }
+
+    if config.resume_podcasts {
+        listeners.add(listener::podcasts::Resumer::new(messages.sender(), &config));
     }
 
     // rpc
     if config.rpc.enabled {
-        let rpc = Rpc::new(config.clone());
+        let rpc = Rpc::new(config.clone(), messages.sender().clock().clone());
         listeners.add(rpc);
-    }
 
-    // start watching the filesystem
-    let watcher = filesystem::watch(messages.sender(), &config)
-        .context("failed to start to watch the filesystem")?;
+        if config.rpc.lyrics {
+            listeners.add(listener::lyrics::Follower::new(messages.sender()));
+        }
+    }
 
     // set up the system tray
     let gtk_handle = tray.then(|| {
         let config = config.clone();
         let tx = messages.sender();
+        let (updates, reciever) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        listeners.add(tray::Forwarder::new(updates));
         // initialize gtk in another thread
         // so this thread can handle messages
         thread::spawn(move || 
-            tray::start(tx, config)
+            tray::start(tx, config, reciever)
                 .unwrap_or_else(|err| error!("failed to start system tray: {err:?}"))
         )
     });
 
     // -- running -- //
 
-    let sender = messages.sender();
-
     // start listening to messages
-    let handle = task::spawn(messages.listen_until_exit(listeners, config.clone()));
-
-    // get initial values by queueing up an update
-    if filesystem::plugin_available(&config).await?.unwrap_or(false) { 
-        sender.attach(); 
-    }
-
-    // finish listening to messages
-    handle.await?;
+    task::spawn(messages.listen_until_exit(listeners, config.clone())).await?;
 
     // -- cleanup -- //
 
     debug!("recieved exit signal");
 
-    // stop watching the filesystem before dropping everything else
-    drop(watcher);
-
     // cleanup
     if let Some(gtk_handle) = gtk_handle {
         glib::idle_add_once(gtk::main_quit);
@@ -0,0 +1,112 @@
+use std::path::Path;
+
+use anyhow::Error;
+
+use crate::{config::{self, Config, Transport}, filesystem::{self, PLUGIN_ACTIVATED_FILE}, daemon};
+
+/// Prints out anything that could stop the handler from working
+pub fn run(config: &Config, config_file: &Path, config_err: Option<&Error>) {
+    println!("config file: {}", config_file.display());
+    if let Some(err) = config_err {
+        problem(&format!("the config failed to parse, so the defaults are being used: {err:?}"));
+    }
+
+    check_communication(config);
+    if let Some(folder) = config_file.parent() {
+        check_instances(config, folder);
+    }
+    check_plugin(config);
+    check_daemon(config);
+    check_wine(config);
+}
+
+fn check_communication(config: &Config) {
+    let directory = Path::new(config.communication.directory.get());
+    println!("\ncommunication directory: {}", directory.display());
+
+    if directory.exists() {
+        match filesystem::check_directory(directory) {
+            Ok(()) => ok("only the current user can access the directory"),
+            Err(err) => problem(&format!("{err}, so the handler refuses to use it")),
+        }
+    } else {
+        ok("the directory doesn't exist yet, it will be created when the handler starts");
+    }
+
+    match dirs::runtime_dir() {
+        Some(runtime) if !directory.starts_with(&runtime) => problem(
+            "the directory isn't in the runtime directory, consider setting communication.directory to \"{runtime_dir}/musicbee-mediakeys\""
+        ),
+        Some(_) => ok("the directory is in the runtime directory"),
+        None => problem("$XDG_RUNTIME_DIR isn't set, so there's no private place for the directory"),
+    }
+
+    // wine maps Z: to the root of the linux filesystem
+    let windows_path = format!("Z:{}\\", directory.display().to_string().replace('/', "\\"));
+    println!("  the plugin has to use the same directory. In MusicBee, go to");
+    println!("  Edit > Preferences > Plugins > Linux Media Controls > Configure");
+    println!("  and set the Root Directory to: {windows_path}");
+
+    if let Transport::Socket { port } = config.communication.transport {
+        println!("  and set the Socket Port to: {port}");
+    }
+}
+
+/// Instances can't share a directory or a socket, or they'd fight over the plugin
+fn check_instances(config: &Config, folder: &Path) {
+    let others: Vec<_> = config::instances(folder).into_iter()
+        .filter(|instance| *instance != config.instance)
+        .filter_map(|instance| config::get(folder, instance.as_deref()).ok())
+        .collect();
+    if others.is_empty() { return; }
+
+    println!("\ninstances:");
+    let name = |other: &Config| other.instance.clone().unwrap_or_else(|| "the main instance".to_owned());
+    let mut clashed = false;
+    for other in &others {
+        if other.communication.directory.get() == config.communication.directory.get() {
+            problem(&format!("{} uses the same communication directory, give each instance its own", name(other)));
+            clashed = true;
+        }
+        if let (Transport::Socket { port }, Transport::Socket { port: other_port }) = (&config.communication.transport, &other.communication.transport) {
+            if port == other_port {
+                problem(&format!("{} uses the same socket port {port}, only one of them can listen on it", name(other)));
+                clashed = true;
+            }
+        }
+    }
+    if !clashed {
+        ok(&format!("no other instance shares the directory or socket, out of {}", others.len()));
+    }
+}
+
+fn check_plugin(config: &Config) {
+    println!("\nplugin:");
+    match std::fs::read_to_string(config.get_comm_path(PLUGIN_ACTIVATED_FILE)).as_deref().map(str::trim) {
+        Ok("true") => ok("the plugin is running"),
+        Ok("false") => ok("the plugin was running, but MusicBee has been closed"),
+        Ok(_) | Err(_) => problem("the plugin hasn't written anything yet, check that it uses the same directory"),
+    }
+}
+
+fn check_daemon(config: &Config) {
+    println!("\ndaemon:");
+    match daemon::get_pid(config) {
+        Ok(Some(pid)) => ok(&format!("the daemon is running with pid {pid}")),
+        Ok(None) => ok("the daemon isn't running, start it with `musicbee_media_controls run`"),
+        Err(err) => problem(&format!("failed to read the pid file: {err:?}")),
+    }
+}
+
+fn check_wine(config: &Config) {
+    println!("\nwine:");
+    let prefix = Path::new(config.commands.wine_prefix.get());
+    if prefix.is_dir() {
+        ok(&format!("found the wine prefix at {}", prefix.display()));
+    } else {
+        problem(&format!("there's no wine prefix at {}, set commands.wine_prefix", prefix.display()));
+    }
+}
+
+fn ok(message: &str) { println!("  ok: {message}"); }
+fn problem(message: &str) { println!("  problem: {message}"); }
@@ -1,75 +1,351 @@
-use std::{path::{Path, PathBuf}, ops::Deref, ffi::OsStr, fs::OpenOptions, time::Duration, io};
+use std::{path::{Path, PathBuf}, ops::Deref, ffi::OsStr, fs::{OpenOptions, DirBuilder, Permissions}, os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt}, time::Duration, collections::{HashMap, hash_map::DefaultHasher}, hash::{Hash, Hasher}, sync::Arc};
 
 use anyhow::{Result, Context};
 use async_trait::async_trait;
 use log::*;
-use souvlaki::*;
-use notify::{Watcher, RecursiveMode, event::{Event, EventKind, ModifyKind}, RecommendedWatcher};
+use notify::{Watcher, RecursiveMode, event::{Event, EventKind, ModifyKind, MetadataKind, RenameMode}, PollWatcher};
 use thiserror::Error;
+use tokio::{sync::mpsc::{self, UnboundedSender, UnboundedReceiver}, time::{self, Instant}, task::JoinHandle};
 use url::Url;
 
-use crate::{config::Config, messages::{MessageSender, Command}, listener::Listener};
+use crate::{config::{Config, WatchMode}, lyrics, messages::{MessageSender, Command, Settings, OutputDevices}, communication::{Action, ReplayGainMode}, media::{Metadata, MediaPlayback, MediaPosition, Queue, QueueTrack, Playlist, SearchResult, Subscription, Episode}, transport::Transport};
 
 pub const METADATA_FILE: &str = "metadata";
 pub const PLAYBACK_FILE: &str = "playback";
 pub const ACTION_FILE: &str = "action";
 pub const PLUGIN_ACTIVATED_FILE: &str = "plugin-activated";
 pub const VOLUME_FILE: &str = "volume";
+pub const OUTPUT_DEVICES_FILE: &str = "output-devices";
+pub const QUEUE_FILE: &str = "queue";
+pub const PLAYLISTS_FILE: &str = "playlists";
+pub const LYRICS_FILE: &str = "lyrics";
+/// Written by the plugin when it's asked to search, but nothing watches it
+pub const SEARCH_RESULTS_FILE: &str = "search-results";
+/// Written by the plugin when it's asked for the podcasts, but nothing watches it
+pub const PODCASTS_FILE: &str = "podcasts";
 
-pub fn watch(message_sender: MessageSender, config: &Config) -> Result<RecommendedWatcher> {
-    let communication_directory = config.communication.directory.clone();
+/// Files written by the plugin that the handler reacts to
+const WATCHED_FILES: [&str; 8] = [
+    METADATA_FILE, PLAYBACK_FILE, VOLUME_FILE, OUTPUT_DEVICES_FILE, QUEUE_FILE, PLAYLISTS_FILE, LYRICS_FILE,
+    PLUGIN_ACTIVATED_FILE,
+];
+/// Files that get reread on a full update
+const UPDATED_FILES: [&str; 7] = [
+    METADATA_FILE, PLAYBACK_FILE, VOLUME_FILE, OUTPUT_DEVICES_FILE, QUEUE_FILE, PLAYLISTS_FILE, LYRICS_FILE,
+];
 
-    let mut watcher = notify::recommended_watcher(move |event| handle_event(event, &message_sender))?;
-    watcher.watch(Path::new(&communication_directory), RecursiveMode::NonRecursive)?;
+/// How long a file has to go without being modified before it gets read
+///
+/// A single write from the plugin fires several modify events,
+/// so this makes sure the file is only read once
+const SETTLE_WINDOW: Duration = Duration::from_millis(30);
+/// How long to wait before reading a file again if it failed to parse
+const RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// Written to check that inotify works in the communication directory
+const PROBE_FILE: &str = ".watch-probe";
+/// How long to wait to hear about the probe file before giving up on inotify
+const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+/// How often to poll the directory if inotify fails on its own
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Something the watcher noticed in the communication directory
+pub enum Change {
+    /// One of the [watched files](WATCHED_FILES) was modified
+    File(&'static str),
+    /// The communication directory was created again after being removed
+    Recreated,
+}
+
+pub async fn watch(changes: UnboundedSender<Change>, config: &Config) -> Result<Box<dyn Watcher + Send>> {
+    let directory = Path::new(config.communication.directory.get());
+
+    let watcher = match config.communication.watch_mode {
+        WatchMode::Inotify => {
+            info!("watching {} with inotify", directory.display());
+            inotify_watcher(changes, directory)?
+        },
+        WatchMode::Poll { interval } => {
+            warn!("polling {} every {interval:?}", directory.display());
+            poll_watcher(changes, directory, interval)?
+        },
+        WatchMode::Auto => match probe_inotify(changes.clone(), directory).await {
+            Ok(watcher) => {
+                info!("watching {} with inotify", directory.display());
+                watcher
+            },
+            Err(err) => {
+                warn!(
+                    "inotify failed to watch {}, falling back to polling every {DEFAULT_POLL_INTERVAL:?}: {err:?}",
+                    directory.display()
+                );
+                poll_watcher(changes, directory, DEFAULT_POLL_INTERVAL)?
+            },
+        },
+    };
 
     Ok(watcher)
 }
 
-fn handle_event(event: notify::Result<Event>, sender: &MessageSender) {
-    let Ok(event) = event else { return };
+/// Watches the directory along with its parent,
+/// so the directory can be seen if it gets removed and created again
+fn arm(watcher: &mut dyn Watcher, directory: &Path) -> notify::Result<()> {
+    if let Some(parent) = directory.parent() {
+        watcher.watch(parent, RecursiveMode::NonRecursive)?;
+    }
+    watcher.watch(directory, RecursiveMode::NonRecursive)
+}
+
+fn inotify_watcher(changes: UnboundedSender<Change>, directory: &Path) -> Result<Box<dyn Watcher + Send>> {
+    let watched_directory = directory.to_owned();
+    let mut watcher = notify::recommended_watcher(move |event| handle_event(event, &watched_directory, &changes))?;
+    arm(&mut watcher, directory)?;
+    Ok(Box::new(watcher))
+}
+
+fn poll_watcher(changes: UnboundedSender<Change>, directory: &Path, interval: Duration) -> Result<Box<dyn Watcher + Send>> {
+    let config = notify::Config::default().with_poll_interval(interval);
+    let watched_directory = directory.to_owned();
+    let mut watcher = PollWatcher::new(move |event| handle_event(event, &watched_directory, &changes), config)?;
+    arm(&mut watcher, directory)?;
+    Ok(Box::new(watcher))
+}
+
+/// Watches the directory with inotify, making sure that it actually recieves events
+///
+/// Some FUSE and network mounts accept the watch, but never send anything, 
+/// so this writes a probe file and waits to hear about it
+async fn probe_inotify(changes: UnboundedSender<Change>, directory: &Path) -> Result<Box<dyn Watcher + Send>> {
+    let (probed, mut probe) = mpsc::unbounded_channel();
+
+    let watched_directory = directory.to_owned();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        if let Ok(event) = &event {
+            if event.paths.iter().any(|path| path.file_name() == Some(OsStr::new(PROBE_FILE))) {
+                let _ = probed.send(());
+            }
+        }
+        handle_event(event, &watched_directory, &changes);
+    })?;
+    arm(&mut watcher, directory)?;
+
+    let probe_file = directory.join(PROBE_FILE);
+    tokio::fs::write(&probe_file, "").await
+        .context("failed to write the probe file")?;
+    let probed = time::timeout(PROBE_TIMEOUT, probe.recv()).await;
+    let _ = tokio::fs::remove_file(&probe_file).await;
 
-    // FIX: remove repeats
-    if let EventKind::Modify(ModifyKind::Data(_)) = event.kind {
+    probed.context("inotify didn't report any events")?;
+    Ok(Box::new(watcher))
+}
+
+fn handle_event(event: notify::Result<Event>, directory: &Path, changes: &UnboundedSender<Change>) {
+    let event = match event {
+        Ok(event) => event,
+        Err(err) => {
+            error!("failed to watch {}: {err}", directory.display());
+            return;
+        }
+    };
+
+    // the reciever only hangs up when the daemon is exiting, so send errors are ignored
+
+    // events from the parent directory are only needed to see the directory itself
+    if event.paths.iter().any(|path| path == directory) {
+        match event.kind {
+            EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+                let _ = changes.send(Change::Recreated);
+            },
+            EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) =>
+                warn!("{} was removed, waiting for it to come back", directory.display()),
+            _ => (),
+        }
+        return;
+    }
+
+    // the poll watcher only reports data changes when the write time stays the same
+    if let EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Metadata(MetadataKind::WriteTime)) = event.kind {
         let file_names = event.paths.iter()
             .map(Deref::deref)
+            .filter(|path| path.parent() == Some(directory))
             .filter_map(Path::file_name)
-            .filter_map(OsStr::to_str);
+            .filter_map(OsStr::to_str)
+            .filter_map(|name| WATCHED_FILES.into_iter().find(|file| *file == name));
 
         for file_name in file_names {
-            match file_name {
-                METADATA_FILE => sender.update_metadata(),
-                PLAYBACK_FILE => sender.update_playback(),
-                VOLUME_FILE => sender.update_volume(),
-                PLUGIN_ACTIVATED_FILE => sender.update_plugin_activation(),
-                _ => {},
-            }
+            let _ = changes.send(Change::File(file_name));
         }
     }
 }
 
-pub struct Filesystem { sender: MessageSender }
+/// Follows the changes from the watcher
+///
+/// Modify events are coalesced for each file, 
+/// requesting an update once the file has [settled](SETTLE_WINDOW)
+async fn follow_changes(
+    mut changes: UnboundedReceiver<Change>,
+    mut watcher: Box<dyn Watcher + Send>,
+    sender: MessageSender,
+    config: Arc<Config>,
+) {
+    let mut pending: HashMap<&'static str, Instant> = HashMap::new();
+
+    loop {
+        let deadline = pending.values().min().copied();
+
+        tokio::select! {
+            change = changes.recv() => match change {
+                Some(Change::File(file_name)) => {
+                    pending.insert(file_name, Instant::now() + SETTLE_WINDOW);
+                },
+                Some(Change::Recreated) => {
+                    warn!("{} was created again, watching it again", config.communication.directory);
+                    match rewatch(&mut *watcher, &config) {
+                        Ok(()) => sender.update(),
+                        Err(err) => error!("failed to watch the new communication directory: {err:?}"),
+                    }
+                },
+                None => break,
+            },
+            () = time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                let now = Instant::now();
+                pending.retain(|file_name, deadline| {
+                    let settled = *deadline <= now;
+                    if settled { request_update(file_name, &sender); }
+                    !settled
+                });
+            },
+        }
+    }
+}
+
+/// Sets the communication directory back up after it was created again
+fn rewatch(watcher: &mut dyn Watcher, config: &Config) -> Result<()> {
+    create_file_structure(config)
+        .context("failed to recreate the file structure")?;
+
+    // the old watch went away with the old directory
+    let directory = Path::new(config.communication.directory.get());
+    let _ = watcher.unwatch(directory);
+    watcher.watch(directory, RecursiveMode::NonRecursive)
+        .context("failed to watch the directory")?;
+    Ok(())
+}
+
+fn request_update(file_name: &str, sender: &MessageSender) {
+    match file_name {
+        METADATA_FILE => sender.update_metadata(),
+        PLAYBACK_FILE => sender.update_playback(),
+        VOLUME_FILE => sender.update_volume(),
+        OUTPUT_DEVICES_FILE => sender.update_output_devices(),
+        QUEUE_FILE => sender.update_queue(),
+        PLAYLISTS_FILE => sender.update_playlists(),
+        LYRICS_FILE => sender.update_lyrics(),
+        PLUGIN_ACTIVATED_FILE => sender.update_plugin_activation(),
+        _ => {},
+    }
+}
+
+fn hash(contents: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Talks to the plugin through files in the communication directory
+pub struct Filesystem {
+    sender: MessageSender,
+    config: Arc<Config>,
+    /// Owns the watcher, following its changes
+    follower: Option<JoinHandle<()>>,
+    /// The hash of each file's contents from when it was last parsed
+    parsed: HashMap<&'static str, u64>,
+}
 
 impl Filesystem {
-    pub const fn new(sender: MessageSender) -> Self {
-        Self { sender }
+    pub fn new(sender: MessageSender, config: Arc<Config>) -> Self {
+        Self { sender, config, follower: None, parsed: HashMap::new() }
+    }
+
+    /// Reads and parses a comm file
+    ///
+    /// Unless forced, files that haven't changed since they were last parsed are skipped
+    async fn refresh(&mut self, name: &'static str, force: bool, config: &Config) -> Result<()> {
+        let contents = config.read_comm_file(name).await
+            .with_context(|| format!("failed to read the {name} file"))?;
+        let mut parsed = hash(&contents);
+
+        if !force && self.parsed.get(name) == Some(&parsed) {
+            trace!("{name} hasn't changed, skipping");
+            return Ok(());
+        }
+
+        if let Err(err) = parse(name, &contents, &self.sender, config) {
+            // the file might have been read while the plugin was still writing it,
+            // so give it one more chance
+            trace!("failed to parse {name}, retrying: {err:?}");
+            time::sleep(RETRY_DELAY).await;
+
+            let contents = config.read_comm_file(name).await
+                .with_context(|| format!("failed to read the {name} file"))?;
+            parse(name, &contents, &self.sender, config)?;
+            parsed = hash(&contents);
+        }
+
+        self.parsed.insert(name, parsed);
+        Ok(())
     }
 }
 
 #[async_trait]
-impl Listener for Filesystem {
+impl Transport for Filesystem {
+    async fn start(&mut self, config: &Config) -> Result<()> {
+        let (changes, reciever) = mpsc::unbounded_channel();
+        let watcher = watch(changes, config).await
+            .context("failed to start to watch the filesystem")?;
+        self.follower = Some(tokio::spawn(
+            follow_changes(reciever, watcher, self.sender.clone(), self.config.clone())
+        ));
+
+        // get initial values by queueing up an update
+        if plugin_available(config).await?.unwrap_or(false) {
+            self.sender.plugin_activated(true);
+        }
+        Ok(())
+    }
+
+    async fn update(&mut self, config: &Config) -> Result<()> {
+        // the listeners need everything again, even if it hasn't changed
+        for file_name in UPDATED_FILES {
+            self.refresh(file_name, true, config).await
+                .unwrap_or_else(|err| error!("failed to update {file_name}: {err:?}"));
+        }
+        Ok(())
+    }
+
+    async fn send(&mut self, action: &Action, config: &Config) -> Result<()> {
+        action.write(config).await.context("failed to run action")?;
+        Ok(())
+    }
+
     async fn handle(&mut self, command: Command, config: &Config) -> Result<()> {
-        let Self { sender } = self;
         match command {
-            Command::Update => 
-                update(sender, config).await.context("failed to update handlers")?,
             Command::UpdateMetadata => 
-                update_metadata(sender, config).await.context("failed to update metadata")?,
+                self.refresh(METADATA_FILE, false, config).await.context("failed to update metadata")?,
             Command::UpdatePlayback => 
-                update_playback(sender, config).await.context("failed to update playback")?,
+                self.refresh(PLAYBACK_FILE, false, config).await.context("failed to update playback")?,
             Command::UpdateVolume => 
-                update_volume(sender, config).await.context("failed to update volume")?,
+                self.refresh(VOLUME_FILE, false, config).await.context("failed to update volume")?,
+            Command::UpdateOutputDevices => 
+                self.refresh(OUTPUT_DEVICES_FILE, false, config).await.context("failed to update output devices")?,
+            Command::UpdateQueue => 
+                self.refresh(QUEUE_FILE, false, config).await.context("failed to update queue")?,
+            Command::UpdatePlaylists => 
+                self.refresh(PLAYLISTS_FILE, false, config).await.context("failed to update playlists")?,
+            Command::UpdateLyrics => 
+                self.refresh(LYRICS_FILE, false, config).await.context("failed to update lyrics")?,
             Command::UpdatePluginActivation => 
-                plugin_activation_changed(sender, config).await.context("failed to update plugin activation")?,
+                self.refresh(PLUGIN_ACTIVATED_FILE, false, config).await.context("failed to update plugin activation")?,
             _ => (),
         }
         Ok(())
@@ -78,8 +354,25 @@ impl Listener for Filesystem {
     fn name(&self) -> &'static str { "filesystem" }
 }
 
-pub fn create_file_structure(config: &Config) -> io::Result<()> {
-    std::fs::create_dir_all(Path::new(&config.communication.directory))?;
+impl Drop for Filesystem {
+    fn drop(&mut self) {
+        // stops watching the filesystem as well
+        if let Some(follower) = self.follower.take() {
+            follower.abort();
+        }
+    }
+}
+
+pub fn create_file_structure(config: &Config) -> Result<()> {
+    let directory = Path::new(config.communication.directory.get());
+
+    // only the current user should be able to send actions to the player
+    DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(directory)
+        .context("failed to create the communication directory")?;
+    check_directory(directory)?;
 
     OpenOptions::new()
         .write(true).create(true).truncate(false)
@@ -90,12 +383,77 @@ pub fn create_file_structure(config: &Config) -> io::Result<()> {
     OpenOptions::new()
         .write(true).create(true).truncate(false)
         .open(config.get_comm_path(VOLUME_FILE))?;
+    OpenOptions::new()
+        .write(true).create(true).truncate(false)
+        .open(config.get_comm_path(OUTPUT_DEVICES_FILE))?;
+    OpenOptions::new()
+        .write(true).create(true).truncate(false)
+        .open(config.get_comm_path(QUEUE_FILE))?;
+    OpenOptions::new()
+        .write(true).create(true).truncate(false)
+        .open(config.get_comm_path(PLAYLISTS_FILE))?;
+    OpenOptions::new()
+        .write(true).create(true).truncate(false)
+        .open(config.get_comm_path(LYRICS_FILE))?;
+    OpenOptions::new()
+        .write(true).create(true).truncate(false)
+        .open(config.get_comm_path(SEARCH_RESULTS_FILE))?;
+    OpenOptions::new()
+        .write(true).create(true).truncate(false)
+        .open(config.get_comm_path(PODCASTS_FILE))?;
     OpenOptions::new()
         .write(true).create(true).truncate(false)
         .open(config.get_comm_path(PLUGIN_ACTIVATED_FILE))?;
     Ok(())
 }
 
+#[derive(Debug, Error)]
+pub enum UnsafeDirectory {
+    #[error("{0} is a symlink")]
+    Symlink(String),
+    #[error("{0} isn't a directory")]
+    NotDirectory(String),
+    #[error("{0} is owned by another user (uid {1})")]
+    NotOwned(String, u32),
+    #[error("{0} can be written to by other users (mode {1:o})")]
+    Writable(String, u32),
+}
+
+/// Makes sure that no other user can control the player through the communication directory
+///
+/// Directories that other users can only read from are fixed
+pub fn check_directory(directory: &Path) -> Result<()> {
+    let name = || directory.display().to_string();
+    let metadata = std::fs::symlink_metadata(directory)
+        .context("failed to read the communication directory")?;
+
+    if metadata.file_type().is_symlink() {
+        Err(UnsafeDirectory::Symlink(name()))?;
+    }
+    if !metadata.is_dir() {
+        Err(UnsafeDirectory::NotDirectory(name()))?;
+    }
+
+    // SAFETY: geteuid can't fail
+    let user = unsafe { libc::geteuid() };
+    if metadata.uid() != user {
+        Err(UnsafeDirectory::NotOwned(name(), metadata.uid()))?;
+    }
+
+    let mode = metadata.mode() & 0o777;
+    // other users could have already put their own files in here
+    if mode & 0o022 != 0 {
+        Err(UnsafeDirectory::Writable(name(), mode))?;
+    }
+    if mode & 0o077 != 0 {
+        warn!("{} can be read by other users, restricting it to the current user", name());
+        std::fs::set_permissions(directory, Permissions::from_mode(0o700))
+            .context("failed to restrict the communication directory")?;
+    }
+
+    Ok(())
+}
+
 #[derive(Debug, Error)]
 pub enum MalformedFile {
     #[error("playback value '{0}' not found")]
@@ -104,48 +462,98 @@ pub enum MalformedFile {
     Metadata(String),
     #[error("got malformed volume: '{0}'")]
     Volume(String),
+    #[error("got malformed settings: '{0}'")]
+    Settings(String),
+    #[error("got malformed queue track: '{0}'")]
+    Queue(String),
+    #[error("got malformed playlist: '{0}'")]
+    Playlist(String),
+    #[error("got malformed search result: '{0}'")]
+    SearchResult(String),
+    #[error("got malformed podcast: '{0}'")]
+    Podcast(String),
 }
 
 pub async fn plugin_available(config: &Config) -> Result<Option<bool>> {
     let text = config.read_comm_file(PLUGIN_ACTIVATED_FILE).await
         .context("failed to read plugin availability")?;
 
+    parse_plugin_availability(&text)
+}
+
+fn parse_plugin_availability(text: &str) -> Result<Option<bool>> {
     // empty files are normal when they're being created
     if text.is_empty() { return Ok(None); }
 
-    Ok(Some(text.parse().context("failed to parse plugin availability")?))
+    Ok(Some(text.trim().parse().context("failed to parse plugin availability")?))
 }
 
-async fn plugin_activation_changed(send: &MessageSender, config: &Config) -> Result<()> {
-    if let Some(activated) = plugin_available(config).await? {
-        send.plugin_activated(activated);
+/// Parses the contents of a comm file, sending what was found
+pub fn parse(name: &str, contents: &str, send: &MessageSender, config: &Config) -> Result<()> {
+    match name {
+        METADATA_FILE => parse_metadata(contents, send, config),
+        PLAYBACK_FILE => parse_playback(contents, send),
+        VOLUME_FILE => parse_volume(contents, send),
+        OUTPUT_DEVICES_FILE => {
+            // empty files are normal when they're being created
+            if !contents.is_empty() {
+                send.output_devices(parse_output_devices(contents));
+            }
+            Ok(())
+        },
+        PLUGIN_ACTIVATED_FILE => {
+            if let Some(activated) = parse_plugin_availability(contents)? {
+                send.plugin_activated(activated);
+            }
+            Ok(())
+        },
+        QUEUE_FILE => {
+            // empty files are normal when they're being created
+            if !contents.is_empty() {
+                send.queue(parse_queue(contents, config)?);
+            }
+            Ok(())
+        },
+        // an empty file means that there aren't any playlists
+        PLAYLISTS_FILE => {
+            send.playlists(parse_playlists(contents)?);
+            Ok(())
+        },
+        LYRICS_FILE => {
+            // empty files are normal when they're being created
+            if !contents.is_empty() {
+                send.lyrics(lyrics::load(contents, config));
+            }
+            Ok(())
+        },
+        _ => {
+            warn!("got contents for unknown file '{name}'");
+            Ok(())
+        },
     }
-    Ok(())
 }
 
-async fn update(send: &MessageSender, config: &Config) -> Result<()> {
-    let (metadata, playback, volume) = futures::join!(
-        update_metadata(send, config),
-        update_playback(send, config),
-        update_volume(send, config),
-    );
-    metadata.context("failed to update metadata")?;
-    playback.context("failed to update playback")?;
-    volume.context("failed to update volume")?;
+fn parse_playback(playback: &str, send: &MessageSender) -> Result<()> {
+    let Some((playback, settings)) = parse_playback_contents(playback)? else { return Ok(()) };
+
+    if let Some(settings) = settings {
+        send.settings(settings);
+    }
+    send.playback(playback);
     Ok(())
 }
 
-async fn update_playback(send: &MessageSender, config: &Config) -> Result<()> {
-    let playback = config.read_comm_file(PLAYBACK_FILE).await
-        .context("failed to read the playback file")?;
-
+/// Parses the playback file, along with the settings that newer plugins send with it
+///
+/// Returns None when there's nothing to update yet
+pub fn parse_playback_contents(playback: &str) -> Result<Option<(MediaPlayback, Option<Settings>)>> {
     // empty files are normal when they're being created
-    if playback.is_empty() { return Ok(()); }
+    if playback.is_empty() { return Ok(None); }
 
     // split data by lines
     let lines: Vec<_> = playback.lines().collect();
 
-    if let [ playback, progress ] = lines[..] {
+    if let [ playback, progress, ref settings @ .. ] = lines[..] {
         let progress = progress.parse()
             .map(Duration::from_millis)
             .map(|p| Some(MediaPosition(p)))
@@ -157,57 +565,328 @@ async fn update_playback(send: &MessageSender, config: &Config) -> Result<()> {
             "stopped" => MediaPlayback::Stopped,
             "paused"  => MediaPlayback::Paused { progress },
             "playing" => MediaPlayback::Playing { progress },
-            "loading" => return Ok(()),
+            "loading" => return Ok(None),
             _ => {
                 return Err(MalformedFile::Playback(playback.trim().to_owned()))?;
             }
         };
 
-        send.playback(playback);
+        // older plugins only send the playback
+        let settings = if settings.is_empty() { None } else { Some(parse_settings(settings)?) };
+
+        Ok(Some((playback, settings)))
     } else {
-        return Err(MalformedFile::Playback(playback.trim().to_owned()))?;
+        Err(MalformedFile::Playback(playback.trim().to_owned()))?
     }
-    Ok(())
 }
 
-async fn update_metadata(send: &MessageSender, config: &Config) -> Result<()> {
-    let metadata = config.read_comm_file(METADATA_FILE).await
-        .context("failed to read the metadata file")?;
+fn parse_settings(settings: &[&str]) -> Result<Settings> {
+    let malformed = || MalformedFile::Settings(settings.join("\n"));
+    let parse_bool = |value: &str| value.trim().parse().map_err(|_| malformed());
+
+    let [ shuffle, repeat, ref rest @ .. ] = settings[..] else {
+        return Err(malformed())?;
+    };
+
+    let mut parsed = Settings {
+        shuffle: parse_bool(shuffle)?,
+        repeat: repeat.trim().parse().map_err(|_| malformed())?,
+        muted: false,
+        stop_after_current: false,
+        auto_dj: false,
+        equaliser: false,
+        dsp: false,
+        crossfade: false,
+        replay_gain: ReplayGainMode::Off,
+    };
+
+    // older plugins only send shuffle and repeat
+    if let [ muted, stop_after_current, auto_dj, ref rest @ .. ] = rest[..] {
+        parsed.muted = parse_bool(muted)?;
+        parsed.stop_after_current = parse_bool(stop_after_current)?;
+        parsed.auto_dj = parse_bool(auto_dj)?;
+
+        if let [ equaliser, dsp, crossfade, replay_gain, .. ] = rest {
+            parsed.equaliser = parse_bool(equaliser)?;
+            parsed.dsp = parse_bool(dsp)?;
+            parsed.crossfade = parse_bool(crossfade)?;
+            parsed.replay_gain = replay_gain.trim().parse().map_err(|_| malformed())?;
+        }
+    }
+
+    Ok(parsed)
+}
 
+fn parse_metadata(metadata: &str, send: &MessageSender, config: &Config) -> Result<()> {
     // empty files are normal when they're being created
     if metadata.is_empty() { return Ok(()); }
 
-    // split data by lines
-    let lines: Vec<_> = metadata.lines().collect();
+    // split data by lines, keeping empty lines at the end
+    let lines: Vec<_> = metadata.split('\n').map(|line| line.trim_end_matches('\r')).collect();
 
-    if let [ title, album, artist, cover_url, duration ] = lines[..] {
-        let duration = duration.parse()
-            .map(Duration::from_millis)
-            .context("failed to parse the song duration as a number")?;
-
-        send
-            .metadata(MediaMetadata {
-                title: Some(title),
-                album: Some(album),
-                artist: Some(artist),
-                cover_url: map_cover(cover_url, config, artist, title).as_deref(),
-                duration: Some(duration),
-            });
-        Ok(())
-    } else {
-        Err(MalformedFile::Metadata(metadata))?
+    let [ title, album, artist, cover_url, duration, ref extra @ .. ] = lines[..] else {
+        return Err(MalformedFile::Metadata(metadata.to_owned()))?;
+    };
+
+    // streams don't have a length, so they can be 0 or -1
+    let duration: i64 = duration.parse()
+        .context("failed to parse the song duration as a number")?;
+
+    let mut parsed = Metadata {
+        title: Some(title.to_owned()),
+        album: Some(album.to_owned()),
+        artist: Some(artist.to_owned()),
+        cover_url: map_cover(cover_url, config, artist, title),
+        duration: u64::try_from(duration).ok().map(Duration::from_millis),
+        ..Metadata::default()
+    };
+
+    // older plugins only send the fields above
+    match extra {
+        [] => parsed.artists = split_multi_value(artist),
+        [ album_artist, artists, track_number, disc_number, genres, path, ref rest @ .. ] => {
+            parsed.album_artist = non_empty(album_artist);
+            parsed.artists = split_multi_value(if artists.is_empty() { artist } else { artists });
+            parsed.track_number = parse_number(track_number);
+            parsed.disc_number = parse_number(disc_number);
+            parsed.genres = split_multi_value(genres);
+            // stream urls aren't in the prefix
+            parsed.path = non_empty(path).map(|path| if is_stream_url(&path) { path } else { config.map_filename(&path) });
+
+            if let [ rating, loved, ref rest @ .. ] = rest[..] {
+                parsed.rating = parse_rating(rating);
+                parsed.loved = loved == "true";
+
+                if let [ podcast, ref description @ .. ] = rest[..] {
+                    parsed.podcast = non_empty(podcast);
+                    parsed.description = non_empty(description.join("\n").trim());
+                }
+            }
+        },
+        _ => Err(MalformedFile::Metadata(metadata.to_owned()))?,
+    }
+
+    // podcasts can be streamed too, but they're still episodes
+    let streamed = duration <= 0 || parsed.path.as_deref().is_some_and(is_stream_url);
+    if streamed && parsed.podcast.is_none() {
+        parsed = stream_metadata(parsed);
+    }
+
+    send.metadata(parsed);
+    Ok(())
+}
+
+/// Whether the file is played from the web instead of from a file
+fn is_stream_url(path: &str) -> bool {
+    path.contains("://") && !path.starts_with("file://")
+}
+
+/// Moves the fields of a stream around to match a track
+///
+/// MusicBee puts the station's name in the title and the stream's title in the artist,
+/// which is usually `artist - title`
+fn stream_metadata(metadata: Metadata) -> Metadata {
+    let station = metadata.title.as_deref().and_then(non_empty);
+    let stream_title = metadata.artist.as_deref().and_then(non_empty);
+
+    let (artist, title) = match stream_title.as_deref().map(|title| title.split_once(" - ")) {
+        Some(Some((artist, title))) => (non_empty(artist), non_empty(title)),
+        Some(None) => (None, stream_title),
+        // nothing is known about what's playing yet
+        None => (None, station.clone()),
+    };
+
+    Metadata {
+        artists: artist.iter().cloned().collect(),
+        artist,
+        title,
+        album: metadata.album.as_deref().and_then(non_empty),
+        duration: None,
+        station,
+        stream: true,
+        ..metadata
     }
 }
 
-async fn update_volume(send: &MessageSender, config: &Config) -> Result<()> {
-    let volume = config.read_comm_file(VOLUME_FILE).await
-        .context("failed to read the volume file")?;
+/// Splits up a tag with multiple values
+///
+/// MusicBee seperates the values with null characters, a `;` can be part of a value
+fn split_multi_value(value: &str) -> Vec<String> {
+    value.split('\0')
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    (!value.is_empty()).then(|| value.to_owned())
+}
+
+/// Parses a star rating out of 5 into a rating out of 100
+fn parse_rating(rating: &str) -> Option<u8> {
+    let stars: f32 = rating.trim().parse().ok()?;
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // it's clamped
+    Some((stars.clamp(0.0, 5.0) * 20.0).round() as u8)
+}
+
+/// Parses numbers like track numbers, which can look like `3/12`
+fn parse_number(value: &str) -> Option<u32> {
+    value.split('/').next()?.trim().parse().ok()
+}
+
+/// Parses the active device on the first line, followed by every device
+pub fn parse_output_devices(contents: &str) -> OutputDevices {
+    let mut lines = contents.lines().map(|line| line.trim_end_matches('\r'));
+    let active = lines.next().and_then(non_empty);
+    let devices = lines.filter_map(non_empty).collect();
+    OutputDevices { active, devices }
+}
+
+/// Parses the current index on the first line, followed by a track on each line
+///
+/// The fields of each track are seperated by tabs
+pub fn parse_queue(contents: &str, config: &Config) -> Result<Queue> {
+    let mut lines = contents.lines().map(|line| line.trim_end_matches('\r'));
+
+    let current: isize = lines.next().unwrap_or_default().trim().parse()
+        .context("failed to parse the current index as a number")?;
+    // there's no current track when the list is empty
+    let current = usize::try_from(current).ok();
+
+    let tracks = lines
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let fields: Vec<_> = line.split('\t').collect();
+            let [ index, title, artist, album, path, .. ] = fields[..] else {
+                return Err(MalformedFile::Queue(line.to_owned()))?;
+            };
+
+            let index = index.parse()
+                .map_err(|_| MalformedFile::Queue(line.to_owned()))?;
+            let metadata = Metadata {
+                title: non_empty(title),
+                album: non_empty(album),
+                artist: non_empty(artist),
+                artists: split_multi_value(artist),
+                path: non_empty(path).map(|path| config.map_filename(&path)),
+                ..Metadata::default()
+            };
+            Ok(QueueTrack { index, metadata })
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(Queue { current, tracks })
+}
+
+/// Parses a playlist on each line, as the url and name seperated by a tab
+pub fn parse_playlists(contents: &str) -> Result<Vec<Playlist>> {
+    contents.lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let Some((url, name)) = line.split_once('\t') else {
+                return Err(MalformedFile::Playlist(line.to_owned()))?;
+            };
+            Ok(Playlist { url: url.to_owned(), name: name.to_owned() })
+        })
+        .collect()
+}
+
+/// The last line of an answer to a request
+const ANSWER_END: &str = "end";
 
+/// The lines of an answer to a request, without the end
+///
+/// Returns [`None`] until the plugin has written the end, since the file can be read while it's
+/// still being written
+fn answer_lines(contents: &str) -> Option<Vec<&str>> {
+    let mut lines: Vec<_> = contents.lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.is_empty())
+        .collect();
+    (lines.pop()? == ANSWER_END).then_some(lines)
+}
+
+/// Parses the id of the search on the first line, followed by a result on each line
+///
+/// Returns [`None`] until the whole file is written
+pub fn parse_search_results(contents: &str, config: &Config) -> Result<Option<(u32, Vec<SearchResult>)>> {
+    let Some(lines) = answer_lines(contents) else { return Ok(None) };
+
+    let mut lines = lines.into_iter();
+    let id = lines.next().unwrap_or_default().trim().parse()
+        .context("failed to parse the search id as a number")?;
+
+    let results = lines
+        .map(|line| {
+            let fields: Vec<_> = line.split('\t').collect();
+            let [ file, title, artist, album, album_artist, track_number, year, genres, .. ] = fields[..] else {
+                return Err(MalformedFile::SearchResult(line.to_owned()))?;
+            };
+
+            Ok(SearchResult {
+                file: file.to_owned(),
+                path: config.map_filename(file),
+                title: non_empty(title),
+                artist: non_empty(artist),
+                album: non_empty(album),
+                album_artist: non_empty(album_artist),
+                track_number: parse_number(track_number),
+                year: non_empty(year),
+                genres: split_multi_value(genres),
+            })
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(Some((id, results)))
+}
+
+/// Parses the podcasts that the plugin wrote, along with the id of the request
+///
+/// Returns None until the whole file is written
+pub fn parse_podcasts(contents: &str) -> Result<Option<(u32, Vec<Subscription>)>> {
+    let Some(lines) = answer_lines(contents) else { return Ok(None) };
+
+    let mut lines = lines.into_iter();
+    let id = lines.next().unwrap_or_default().trim().parse()
+        .context("failed to parse the podcasts id as a number")?;
+
+    let mut subscriptions: Vec<Subscription> = Vec::new();
+    for line in lines {
+        let malformed = || MalformedFile::Podcast(line.to_owned());
+        let fields: Vec<_> = line.split('\t').collect();
+
+        match fields[..] {
+            [ "subscription", id, title, .. ] => subscriptions.push(Subscription {
+                id: id.to_owned(),
+                title: title.to_owned(),
+                episodes: Vec::new(),
+            }),
+            [ "episode", url, title, date, duration, downloaded, .. ] => {
+                // episodes come after their subscription
+                let subscription = subscriptions.last_mut().ok_or_else(malformed)?;
+                subscription.episodes.push(Episode {
+                    url: url.to_owned(),
+                    title: non_empty(title),
+                    date: non_empty(date),
+                    duration: non_empty(duration),
+                    downloaded: downloaded.eq_ignore_ascii_case("true"),
+                });
+            },
+            _ => Err(malformed())?,
+        }
+    }
+
+    Ok(Some((id, subscriptions)))
+}
+
+fn parse_volume(volume: &str, send: &MessageSender) -> Result<()> {
     // empty files are normal when they're being created
     if volume.is_empty() { return Ok(()); }
 
     let volume: f64 = volume.trim().parse()
-        .map_err(|_| MalformedFile::Volume(volume))?;
+        .map_err(|_| MalformedFile::Volume(volume.to_owned()))?;
 
     send.volume(volume);
 
@@ -278,3 +957,376 @@ fn change_cover_capitalization(path: &Path) -> Option<PathBuf> {
 
     Some(path.with_file_name(new))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use tokio::sync::broadcast::Receiver;
+
+    use crate::{config::ReferencedString, messages::Messages, communication::RepeatMode};
+
+    use super::*;
+
+    /// A communication directory of its own, removed once the test is over
+    struct Directory(PathBuf);
+
+    impl Directory {
+        fn new(name: &str) -> Self {
+            let path = env::temp_dir().join(format!("musicbee-mediakeys-test-{}-{name}", process::id()));
+            std::fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+
+        fn write(&self, name: &str, contents: &str) {
+            std::fs::write(self.0.join(name), contents).unwrap();
+        }
+    }
+
+    impl Drop for Directory {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn filesystem(directory: &Directory) -> (Messages, Filesystem, Receiver<Command>, Arc<Config>) {
+        let mut config = Config::default();
+        config.communication.directory = ReferencedString::plain(directory.0.to_str().unwrap());
+        let config = Arc::new(config);
+
+        let messages = Messages::new(config.clone());
+        let commands = messages.sender().subscribe();
+        let filesystem = Filesystem::new(messages.sender(), config.clone());
+        (messages, filesystem, commands, config)
+    }
+
+    /// Every command that's been sent so far
+    fn sent(commands: &mut Receiver<Command>) -> Vec<Command> {
+        std::iter::from_fn(|| commands.try_recv().ok()).collect()
+    }
+
+    fn volumes(commands: &mut Receiver<Command>) -> Vec<f64> {
+        sent(commands).into_iter()
+            .filter_map(|command| if let Command::Volume(volume) = command { Some(volume) } else { None })
+            .collect()
+    }
+
+    fn playbacks(commands: &mut Receiver<Command>) -> Vec<MediaPlayback> {
+        sent(commands).into_iter()
+            .filter_map(|command| if let Command::Playback(playback) = command { Some((*playback).clone()) } else { None })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn unchanged_contents() {
+        let directory = Directory::new("unchanged");
+        let (_messages, mut filesystem, mut commands, config) = filesystem(&directory);
+
+        directory.write(VOLUME_FILE, "0.5");
+        filesystem.refresh(VOLUME_FILE, false, &config).await.unwrap();
+        assert_eq!(volumes(&mut commands), [0.5]);
+
+        filesystem.refresh(VOLUME_FILE, false, &config).await.unwrap();
+        assert!(volumes(&mut commands).is_empty());
+
+        // full updates still send everything
+        filesystem.refresh(VOLUME_FILE, true, &config).await.unwrap();
+        assert_eq!(volumes(&mut commands), [0.5]);
+
+        directory.write(VOLUME_FILE, "0.25");
+        filesystem.refresh(VOLUME_FILE, false, &config).await.unwrap();
+        assert_eq!(volumes(&mut commands), [0.25]);
+    }
+
+    #[tokio::test]
+    async fn truncated_contents() {
+        let directory = Directory::new("truncated");
+        let (_messages, mut filesystem, mut commands, config) = filesystem(&directory);
+
+        // the plugin finishes writing while the handler waits to retry
+        directory.write(PLAYBACK_FILE, "playing");
+        let path = directory.0.join(PLAYBACK_FILE);
+        let writer = tokio::spawn(async move {
+            time::sleep(RETRY_DELAY / 2).await;
+            std::fs::write(path, "playing\n1000\n").unwrap();
+        });
+
+        filesystem.refresh(PLAYBACK_FILE, false, &config).await.unwrap();
+        writer.await.unwrap();
+        let playing = MediaPlayback::Playing { progress: Some(MediaPosition(Duration::from_secs(1))) };
+        assert_eq!(playbacks(&mut commands), [playing]);
+
+        // the retried contents are the ones that were remembered
+        filesystem.refresh(PLAYBACK_FILE, false, &config).await.unwrap();
+        assert!(playbacks(&mut commands).is_empty());
+    }
+
+    #[tokio::test]
+    async fn malformed_contents() {
+        let directory = Directory::new("malformed");
+        let (_messages, mut filesystem, mut commands, config) = filesystem(&directory);
+
+        directory.write(PLAYBACK_FILE, "rewinding\n0\n");
+        assert!(filesystem.refresh(PLAYBACK_FILE, false, &config).await.is_err());
+        // contents that failed aren't remembered, so they aren't skipped
+        assert!(filesystem.refresh(PLAYBACK_FILE, false, &config).await.is_err());
+        assert!(playbacks(&mut commands).is_empty());
+    }
+
+    fn settings(contents: &str) -> Option<Settings> {
+        parse_playback_contents(contents).unwrap().unwrap().1
+    }
+
+    #[test]
+    fn playback_settings() {
+        let everything = settings("playing\n0\ntrue\none\ntrue\nfalse\ntrue\ntrue\nfalse\ntrue\nalbum\n").unwrap();
+        assert_eq!(everything, Settings {
+            shuffle: true,
+            repeat: RepeatMode::One,
+            muted: true,
+            stop_after_current: false,
+            auto_dj: true,
+            equaliser: true,
+            dsp: false,
+            crossfade: true,
+            replay_gain: ReplayGainMode::Album,
+        });
+
+        // older plugins send less, and the rest is left off
+        assert_eq!(settings("playing\n0\n"), None);
+        let shuffle_and_repeat = settings("paused\n0\nfalse\nall\n").unwrap();
+        assert_eq!(shuffle_and_repeat.repeat, RepeatMode::All);
+        assert!(!shuffle_and_repeat.muted);
+        assert_eq!(shuffle_and_repeat.replay_gain, ReplayGainMode::Off);
+        let without_sound = settings("paused\n0\nfalse\nnone\ntrue\ntrue\nfalse\n").unwrap();
+        assert!(without_sound.muted && without_sound.stop_after_current);
+        assert!(!without_sound.equaliser);
+
+        assert!(parse_playback_contents("playing\n0\ntrue\n").is_err());
+        assert!(parse_playback_contents("playing\n0\nmaybe\nall\n").is_err());
+        assert!(parse_playback_contents("playing\n0\ntrue\nsometimes\n").is_err());
+    }
+
+    /// The metadata that the lines of the metadata file turn into
+    fn metadata(lines: &[&str]) -> Result<Metadata> {
+        let config = Config::default();
+        let messages = Messages::new(Arc::new(config.clone()));
+        let mut commands = messages.sender().subscribe();
+        parse_metadata(&lines.join("\n"), &messages.sender(), &config)?;
+
+        Ok(sent(&mut commands).into_iter()
+            .find_map(|command| if let Command::Metadata(metadata) = command { Some((*metadata).clone()) } else { None })
+            .expect("the metadata is sent"))
+    }
+
+    #[test]
+    fn full_metadata() {
+        let parsed = metadata(&[
+            "Title", "Album", "Someone; Someone Else", "", "180000",
+            "Album Artist", "Someone\0Someone Else", "3/12", "1", "Rock\0Pop", r"C:\Music\track.flac",
+            "4.5", "true",
+        ]).unwrap();
+
+        assert_eq!(parsed, Metadata {
+            title: Some("Title".to_owned()),
+            album: Some("Album".to_owned()),
+            artist: Some("Someone; Someone Else".to_owned()),
+            album_artist: Some("Album Artist".to_owned()),
+            artists: vec!["Someone".to_owned(), "Someone Else".to_owned()],
+            track_number: Some(3),
+            disc_number: Some(1),
+            genres: vec!["Rock".to_owned(), "Pop".to_owned()],
+            path: Some(Config::default().map_filename(r"C:\Music\track.flac")),
+            duration: Some(Duration::from_secs(180)),
+            rating: Some(90),
+            loved: true,
+            ..Metadata::default()
+        });
+    }
+
+    #[test]
+    fn older_metadata() {
+        // the plugin used to send only the basics
+        let parsed = metadata(&["Title", "Album", "AC;DC", "", "180000"]).unwrap();
+        assert_eq!(parsed.artists, ["AC;DC"]);
+        assert_eq!(parsed.album_artist, None);
+        assert_eq!(parsed.path, None);
+
+        // and then everything but the rating
+        let parsed = metadata(&["Title", "Album", "Artist", "", "180000", "", "", "", "", "", ""]).unwrap();
+        assert_eq!(parsed.artists, ["Artist"]);
+        assert_eq!(parsed.track_number, None);
+        assert_eq!(parsed.rating, None);
+        assert!(!parsed.loved);
+    }
+
+    #[test]
+    fn malformed_metadata() {
+        assert!(metadata(&["Title", "Album", "Artist", ""]).is_err());
+        assert!(metadata(&["Title", "Album", "Artist", "", "long"]).is_err());
+        assert!(metadata(&["Title", "Album", "Artist", "", "180000", "Album Artist"]).is_err());
+    }
+
+    #[test]
+    fn multi_values() {
+        assert_eq!(split_multi_value("Rock\0 Pop \0\0"), ["Rock", "Pop"]);
+        // only null characters seperate the values
+        assert_eq!(split_multi_value("AC;DC"), ["AC;DC"]);
+        assert!(split_multi_value("").is_empty());
+    }
+
+    #[test]
+    fn queue() {
+        let config = Config::default();
+        let parsed = parse_queue("1\n0\tFirst\tSomeone\tAlbum\tC:\\Music\\first.flac\n1\tSecond\t\t\t\n", &config).unwrap();
+
+        assert_eq!(parsed.current, Some(1));
+        assert_eq!(parsed.tracks, [
+            QueueTrack {
+                index: 0,
+                metadata: Metadata {
+                    title: Some("First".to_owned()),
+                    artist: Some("Someone".to_owned()),
+                    artists: vec!["Someone".to_owned()],
+                    album: Some("Album".to_owned()),
+                    path: Some(config.map_filename(r"C:\Music\first.flac")),
+                    ..Metadata::default()
+                },
+            },
+            QueueTrack { index: 1, metadata: Metadata { title: Some("Second".to_owned()), ..Metadata::default() } },
+        ]);
+
+        // an empty list has nothing playing
+        assert_eq!(parse_queue("-1\n", &config).unwrap(), Queue::default());
+
+        assert!(parse_queue("", &config).is_err());
+        assert!(parse_queue("0\n0\tFirst\n", &config).is_err());
+        assert!(parse_queue("0\nfirst\tFirst\t\t\t\n", &config).is_err());
+    }
+
+    #[test]
+    fn search_results() {
+        let config = Config::default();
+        let contents = "42\nC:\\Music\\one.flac\tOne More Time\tDaft Punk\tDiscovery\tDaft Punk\t1/14\t2001\tHouse\0Electronic\nend\n";
+        let (id, results) = parse_search_results(contents, &config).unwrap().unwrap();
+
+        assert_eq!(id, 42);
+        assert_eq!(results, [SearchResult {
+            file: r"C:\Music\one.flac".to_owned(),
+            path: config.map_filename(r"C:\Music\one.flac"),
+            title: Some("One More Time".to_owned()),
+            artist: Some("Daft Punk".to_owned()),
+            album: Some("Discovery".to_owned()),
+            album_artist: Some("Daft Punk".to_owned()),
+            track_number: Some(1),
+            year: Some("2001".to_owned()),
+            genres: vec!["House".to_owned(), "Electronic".to_owned()],
+        }]);
+
+        assert_eq!(parse_search_results("7\nend\n", &config).unwrap(), Some((7, Vec::new())));
+        assert!(parse_search_results("7\nfile\ttitle\nend\n", &config).is_err());
+    }
+
+    #[test]
+    fn unfinished_answers() {
+        let config = Config::default();
+        // the plugin is still writing these
+        assert_eq!(parse_search_results("", &config).unwrap(), None);
+        assert_eq!(parse_search_results("42\n", &config).unwrap(), None);
+        assert_eq!(parse_search_results("42\nC:\\Music\\one.flac\tOne More", &config).unwrap(), None);
+        assert_eq!(parse_podcasts("42\nsubscription\t1\tShow\n").unwrap(), None);
+    }
+
+    #[test]
+    fn streams() {
+        let stream = |station: &str, stream_title: &str| stream_metadata(Metadata {
+            title: Some(station.to_owned()),
+            artist: Some(stream_title.to_owned()),
+            album: Some(String::new()),
+            duration: Some(Duration::ZERO),
+            ..Metadata::default()
+        });
+
+        let parsed = stream("Radio", "Daft Punk - One More Time");
+        assert_eq!(parsed, Metadata {
+            title: Some("One More Time".to_owned()),
+            artist: Some("Daft Punk".to_owned()),
+            artists: vec!["Daft Punk".to_owned()],
+            station: Some("Radio".to_owned()),
+            stream: true,
+            ..Metadata::default()
+        });
+
+        // titles without an artist are left as they are
+        let parsed = stream("Radio", "The News");
+        assert_eq!((parsed.title.as_deref(), parsed.artist), (Some("The News"), None));
+        // and before anything is known, there's only the station
+        let parsed = stream("Radio", "");
+        assert_eq!((parsed.title.as_deref(), parsed.station.as_deref()), (Some("Radio"), Some("Radio")));
+    }
+
+    #[test]
+    fn streamed_metadata() {
+        let url = "http://radio.example/stream";
+        let parsed = metadata(&["Radio", "", "Daft Punk - One More Time", "", "-1", "", "", "", "", "", url]).unwrap();
+        assert!(parsed.stream);
+        assert_eq!(parsed.title.as_deref(), Some("One More Time"));
+        // urls aren't mapped like files
+        assert_eq!(parsed.path.as_deref(), Some(url));
+
+        // podcasts can be streamed too, but they stay episodes
+        let parsed = metadata(&["Episode", "", "Host", "", "0", "", "", "", "", "", url, "", "false", "Show"]).unwrap();
+        assert!(!parsed.stream);
+        assert_eq!(parsed.title.as_deref(), Some("Episode"));
+    }
+
+    #[test]
+    fn podcasts() {
+        let contents = "9\n\
+            subscription\t1\tA Show\n\
+            episode\thttp://show.example/2.mp3\tSecond\t2024-02-01\t30:00\ttrue\n\
+            episode\thttp://show.example/1.mp3\t\t\t\tfalse\n\
+            subscription\t2\tAnother Show\n\
+            end\n";
+        let (id, subscriptions) = parse_podcasts(contents).unwrap().unwrap();
+
+        assert_eq!(id, 9);
+        assert_eq!(subscriptions, [
+            Subscription {
+                id: "1".to_owned(),
+                title: "A Show".to_owned(),
+                episodes: vec![
+                    Episode {
+                        url: "http://show.example/2.mp3".to_owned(),
+                        title: Some("Second".to_owned()),
+                        date: Some("2024-02-01".to_owned()),
+                        duration: Some("30:00".to_owned()),
+                        downloaded: true,
+                    },
+                    Episode {
+                        url: "http://show.example/1.mp3".to_owned(),
+                        title: None,
+                        date: None,
+                        duration: None,
+                        downloaded: false,
+                    },
+                ],
+            },
+            Subscription { id: "2".to_owned(), title: "Another Show".to_owned(), episodes: Vec::new() },
+        ]);
+
+        // episodes have to come after their subscription
+        assert!(parse_podcasts("9\nepisode\turl\ttitle\tdate\tduration\tfalse\nend\n").is_err());
+        assert!(parse_podcasts("9\nsomething\nend\n").is_err());
+    }
+
+    #[test]
+    fn episode_metadata() {
+        let parsed = metadata(&[
+            "Episode", "", "Host", "", "1800000", "", "", "", "", "", r"C:\Podcasts\episode.mp3", "", "false",
+            "A Show", "The first line", "the second line",
+        ]).unwrap();
+        assert_eq!(parsed.podcast.as_deref(), Some("A Show"));
+        assert_eq!(parsed.description.as_deref(), Some("The first line\nthe second line"));
+    }
+}
@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use tokio::{task::JoinHandle, time};
+
+use crate::{config::Config, messages::{MessageSender, Command}, lyrics::Lyrics};
+
+use super::Listener;
+
+/// Sends the synced line that's being sung as the track plays
+pub struct Follower {
+    sender: MessageSender,
+    lyrics: Option<Arc<Lyrics>>,
+    /// The path of the track that the lyrics are for
+    track: Option<String>,
+    task: Option<JoinHandle<()>>,
+}
+
+#[async_trait]
+impl Listener for Follower {
+    async fn handle(&mut self, command: Command, _: &Config) -> Result<()> {
+        match command {
+            Command::Lyrics(lyrics) => {
+                self.lyrics = lyrics;
+                self.follow();
+            },
+            Command::Metadata(metadata) => {
+                // the new track's lyrics come after its metadata,
+                // so the old ones shouldn't be followed in the meantime
+                if metadata.path != self.track {
+                    self.track.clone_from(&metadata.path);
+                    self.lyrics = None;
+                }
+                self.follow();
+            },
+            // the clock moved, so the next line changed
+            Command::Playback(_) | Command::Seeked(_) => self.follow(),
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str { "lyrics" }
+}
+
+impl Follower {
+    pub const fn new(sender: MessageSender) -> Self {
+        Self { sender, lyrics: None, track: None, task: None }
+    }
+
+    /// Starts following the clock again from where it is now
+    fn follow(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+
+        let sender = self.sender.clone();
+        let lyrics = self.lyrics.clone();
+        self.task = Some(tokio::spawn(async move {
+            let Some(lyrics) = lyrics else {
+                sender.lyrics_line(None);
+                return;
+            };
+
+            loop {
+                let clock = *sender.clock().lock();
+                let position = clock.position();
+                sender.lyrics_line(lyrics.line_at(position).and_then(|index| lyrics.line(index)));
+
+                // paused clocks don't move, so they'll get followed again once they do
+                let Some(next) = lyrics.next_line_after(position).filter(|_| clock.playing()) else { break };
+                time::sleep(next - position).await;
+            }
+        }));
+    }
+}
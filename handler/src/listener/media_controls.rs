@@ -1,160 +1,236 @@
-#![allow(clippy::similar_names)]
-use std::time::Duration;
+use std::{collections::VecDeque, time::Duration};
 
 use anyhow::{Result, Context};
 use async_trait::async_trait;
 use log::*;
-use souvlaki::*;
-use thiserror::Error;
+use tokio::{sync::watch, time::{self, Instant}};
 use url::Url;
+use zbus::ConnectionBuilder;
 
-use crate::{config::Config, communication::Action, messages::{MessageSender, Command}};
+use crate::{config::Config, communication::Action, bindings::{self, Event, Track}, messages::{MessageSender, Command}, media::*, mpris::{Server, SharedState}};
 
 use super::Listener;
 
-#[derive(Debug, Error)]
-pub enum ControlsError {
-    #[error("system error: {0:?}")]
-    System(souvlaki::Error)
-}
-
-impl From<souvlaki::Error> for ControlsError {
-    fn from(v: souvlaki::Error) -> Self {
-        Self::System(v)
-    }
-}
+const BUS_NAME: &str = "com.github.baanan.musicbee_linux";
 
-pub type ControlsResult<T> = Result<T, ControlsError>;
+/// How long a volume that was sent can come back from the plugin as an echo
+const VOLUME_ECHO_WINDOW: Duration = Duration::from_secs(2);
+/// How far an echo can be from the volume that was sent, since the plugin only takes whole percents
+const VOLUME_ECHO_TOLERANCE: f64 = 0.015;
+/// The shortest time between volume actions, volumes set in between are merged
+const VOLUME_INTERVAL: Duration = Duration::from_millis(100);
 
 pub struct Controls {
-    controls: MediaControls,
+    server: Option<Server>,
+    state: SharedState,
     sender: MessageSender,
-    attached: bool,
+    track: Track,
+    /// The newest volume waiting to be sent to the plugin
+    pending_volume: watch::Sender<Option<f64>>,
+    /// Volumes that were sent, as MusicBee's volume, with when they were sent
+    sent_volumes: VecDeque<(f64, Instant)>,
 }
 
 #[async_trait]
 impl Listener for Controls {
     async fn handle(&mut self, command: Command, config: &Config) -> Result<()> {
         match command {
-            Command::Metadata(metadata) => 
-                self.metadata(&(*metadata).as_ref()).context("failed to set metadata")?, 
-            Command::Playback(playback) => 
-                self.playback(&playback).context("failed to set playback")?, 
-            Command::Volume(volume) => 
-                self.volume(volume).context("failed to set volume")?,
-            Command::Attached(true) if !self.attached =>
-                self.attach().context("failed to attach")?,
-            Command::Attached(false) if self.attached => 
-                self.detach().context("failed to detach")?,
-
-            Command::MediaControlEvent(event) =>
-                handle_event(&event, config).await.context("failed to handle event")?,
+            Command::Metadata(metadata) => {
+                self.state.lock().metadata = Some(metadata.clone());
+                self.track.set_metadata(metadata);
+                if let Some(server) = &self.server {
+                    server.metadata_changed().await.context("failed to set metadata")?;
+                }
+            },
+            Command::Playback(playback) => {
+                self.state.lock().playback = playback;
+                if let Some(server) = &self.server {
+                    server.playback_changed().await.context("failed to set playback")?;
+                }
+            },
+            Command::Seeked(position) => {
+                if let Some(server) = &self.server {
+                    server.seeked(position).await.context("failed to send seeked")?;
+                }
+            },
+            Command::Volume(volume) => {
+                // sending it back would fight with whatever is setting the volume
+                if self.is_echo(volume) { return Ok(()); }
+
+                self.state.lock().volume = config.media_controls.volume_curve.controls_volume(volume);
+                if let Some(server) = &self.server {
+                    server.volume_changed().await.context("failed to set volume")?;
+                }
+            },
+            Command::Settings(settings) => {
+                self.state.lock().settings = Some(settings);
+                if let Some(server) = &self.server {
+                    server.settings_changed().await.context("failed to set settings")?;
+                }
+            },
+            Command::Queue(queue) => {
+                self.state.lock().queue = Some(queue);
+                if let Some(server) = &self.server {
+                    server.queue_changed(&self.state).await.context("failed to set queue")?;
+                }
+            },
+            Command::Playlists(playlists) => {
+                let old = std::mem::replace(&mut self.state.lock().playlists, playlists.clone());
+                if let Some(server) = &self.server {
+                    server.playlists_changed(&old, &playlists).await.context("failed to set playlists")?;
+                }
+            },
+            Command::Lyrics(lyrics) => {
+                self.state.lock().lyrics = lyrics;
+                if let Some(server) = &self.server {
+                    server.metadata_changed().await.context("failed to set lyrics")?;
+                }
+            },
+            Command::Attached(true) if self.server.is_none() =>
+                self.attach(config).await.context("failed to attach")?,
+            Command::Attached(false) if self.server.is_some() => 
+                self.detach(),
+
+            Command::MediaControlEvent(event) => match *event {
+                MediaControlEvent::SetVolume(volume) => self.set_volume(volume, config).await.context("failed to set volume")?,
+                ref event => handle_event(event, &self.track, &self.sender, config).context("failed to handle event")?,
+            },
             // NOTE: ignores attaches when already attached and detaches when already detached
             _ => (),
         }
         Ok(())
     }
 
+    async fn start(&mut self, _config: &Config) -> Result<()> {
+        tokio::spawn(send_volumes(self.pending_volume.subscribe(), self.sender.clone()));
+        Ok(())
+    }
+
     fn name(&self) -> &'static str { "media controls" }
 }
 
 impl Controls {
     /// Creates new, unattached media controls
-    pub fn new(sender: MessageSender) -> ControlsResult<Self> {
-        let platform = PlatformConfig {
-            dbus_name: "com.github.baanan.musicbee_linux",
-            display_name: "MusicBee",
-            hwnd: None, // windows only
-        };
-
-        let controls = MediaControls::new(platform)?;
-
-        Ok(Self {
-            controls,
+    pub fn new(sender: MessageSender) -> Self {
+        Self {
+            server: None,
+            state: SharedState::default(),
+            track: Track::new(sender.clock().clone()),
             sender,
-            attached: false,
-        })
+            pending_volume: watch::channel(None).0,
+            sent_volumes: VecDeque::new(),
+        }
     }
 
-    /// Attaches media controls to a handler
-    fn attach(&mut self) -> Result<()> {
-        assert!(!self.attached, "can only attach when not already attached");
+    /// Queues up the volume to be sent to the plugin
+    async fn set_volume(&mut self, volume: f64, config: &Config) -> Result<()> {
+        if !config.media_controls.send_volume { return Ok(()); }
 
-        let sender = self.sender.clone();
-        self.controls
-            .attach(move |event| sender.media_control_event(event))
-            .map_err(ControlsError::from)?;
-        self.attached = true;
+        self.state.lock().volume = volume;
+        // zbus announces the property as soon as it's set, before the state has it,
+        // and the plugin's echo is ignored, so this is the only announcement of the new volume
+        if let Some(server) = &self.server {
+            server.volume_changed().await?;
+        }
 
+        let volume = config.media_controls.volume_curve.player_volume(volume);
+        self.sent_volumes.push_back((volume, Instant::now()));
+        self.pending_volume.send_replace(Some(volume));
         Ok(())
     }
 
-    /// Detatches the media controls from a handler
-    fn detach(&mut self) -> Result<()> {
-        assert!(self.attached, "can only detach when attached");
-
-        self.controls.detach().map_err(ControlsError::from)?;
-        self.attached = false;
+    /// Whether the volume from the plugin is one that was recently sent to it
+    ///
+    /// Echoes, and anything sent before them, are forgotten
+    fn is_echo(&mut self, volume: f64) -> bool {
+        self.sent_volumes.retain(|(_, sent)| sent.elapsed() < VOLUME_ECHO_WINDOW);
 
-        Ok(())
+        let echo = self.sent_volumes.iter()
+            .position(|(sent, _)| (sent - volume).abs() <= VOLUME_ECHO_TOLERANCE);
+        if let Some(echo) = echo {
+            self.sent_volumes.drain(..=echo);
+            trace!("ignoring volume echo: {volume}");
+        }
+        echo.is_some()
     }
 
-    /// Delegate to set the metadata of the controls
-    fn metadata(&mut self, metadata: &MediaMetadata<'_>) -> Result<()> {
-        if self.attached { 
-            self.controls.set_metadata(metadata.clone()).map_err(ControlsError::from)?; 
-        }
+    /// Starts serving the media controls on the bus
+    async fn attach(&mut self, config: &Config) -> Result<()> {
+        assert!(self.server.is_none(), "can only attach when not already attached");
+
+        // a different bus can be used to test the controls without taking over the session
+        let builder = match &config.media_controls.bus_address {
+            Some(address) => ConnectionBuilder::address(address.as_str()),
+            None => ConnectionBuilder::session(),
+        }.context("failed to connect to dbus")?;
+
+        let server = Server::start(builder, &bus_name(config), self.state.clone(), self.sender.clone(), config).await
+            .context("failed to start the mpris server")?;
+        self.server = Some(server);
+
         Ok(())
     }
 
-    /// Delegate to set the volume of the controls
-    fn volume(&mut self, volume: f64) -> Result<()> {
-        if self.attached { 
-            self.controls.set_volume(volume).map_err(ControlsError::from)?;
-        }
-        Ok(())
+    /// Removes the media controls from the bus
+    fn detach(&mut self) {
+        assert!(self.server.is_some(), "can only detach when attached");
+        self.server = None;
     }
+}
 
-    /// Delegate to set the playback of the controls
-    fn playback(&mut self, playback: &MediaPlayback) -> Result<()> {
-        if self.attached { 
-            self.controls.set_playback(playback.clone()).map_err(ControlsError::from)?; 
-        }
-        Ok(())
+/// The bus name, with the instance added on so that instances don't fight over it
+fn bus_name(config: &Config) -> String {
+    let Some(instance) = &config.instance else { return BUS_NAME.to_owned() };
+
+    // bus name elements can't have dashes or start with a number
+    let instance = instance.replace('-', "_");
+    if instance.starts_with(|char: char| char.is_ascii_digit()) {
+        format!("{BUS_NAME}._{instance}")
+    } else {
+        format!("{BUS_NAME}.{instance}")
     }
 }
 
-pub async fn handle_event(event: &MediaControlEvent, config: &Config) -> Result<()> {
+pub fn handle_event(event: &MediaControlEvent, track: &Track, sender: &MessageSender, config: &Config) -> Result<()> {
     #[allow(clippy::enum_glob_use)]
     use MediaControlEvent::*;
     debug!("Recieved control event: {event:?}");
-    match event {
-        Play | Pause | Toggle => config.run_simple_command("/PlayPause")?,
-        Next => config.run_simple_command("/Next")?,
-        Previous => config.run_simple_command("/Previous")?,
-        Stop => config.run_simple_command("/Stop")?,
-        OpenUri(uri) => config.run_command("/Play", Some(map_uri(uri)))?,
-        Seek(direction) => directioned_duration_to_seek(*direction, config.media_controls.seek_amount)?
-            .run(config).await?,
-        SeekBy(direction, duration) => directioned_duration_to_seek(*direction, *duration)?
-            .run(config).await?,
-        SetPosition(MediaPosition(pos)) => Action::Position(*pos).run(config).await?,
-        SetVolume(vol) => if config.media_controls.send_volume { Action::Volume(*vol).run(config).await? },
-        _ => { error!("Event {event:?} not implemented") } // TODO: implement other events
-    }
-    Ok(())
-}
+    let (event, seek) = match event {
+        Play => (Event::Play, None),
+        Pause => (Event::Pause, None),
+        Toggle => (Event::Toggle, None),
+        Next => (Event::Next, None),
+        Previous => (Event::Previous, None),
+        Stop => (Event::Stop, None),
+        SeekBy(SeekDirection::Forward, amount) => (Event::SeekForward, Some(*amount)),
+        SeekBy(SeekDirection::Backward, amount) => (Event::SeekBackward, Some(*amount)),
+        Raise => (Event::Raise, None),
+        Quit => (Event::Quit, None),
 
-fn directioned_duration_to_seek(direction: SeekDirection, duration: Duration) -> Result<Action> {
-    let duration: i32 = duration.as_millis().try_into()
-        .context("failed to convert the seek duration into an i32")?;
-
-    let milis = match direction {
-        SeekDirection::Forward => duration,
-        SeekDirection::Backward => -duration,
+        // the rest of the events set something, so they can't be bound
+        OpenUri(uri) => return Ok(config.run_command("/Play", Some(map_uri(uri)))?),
+        SetPosition(MediaPosition(pos)) => { sender.action(Action::Position(*pos)); return Ok(()) },
+        // the volume is rate limited, so it goes through the controls
+        SetVolume(_) => return Ok(()),
+        SetShuffle(shuffle) => { sender.action(Action::Shuffle(*shuffle)); return Ok(()) },
+        SetRepeat(repeat) => { sender.action(Action::Repeat(*repeat)); return Ok(()) },
+        GoTo(index) => { sender.action(Action::PlayIndex(*index)); return Ok(()) },
+        PlayPlaylist(url) => { sender.action(Action::PlayPlaylist(url.clone())); return Ok(()) },
     };
+    bindings::run(event, seek, track, sender, config)
+}
 
-    Ok(Action::Seek { milis })
+/// Sends the newest volume to the plugin, waiting between each one
+///
+/// Volumes that come in while waiting replace each other, so dragging a slider doesn't flood the plugin
+async fn send_volumes(mut volumes: watch::Receiver<Option<f64>>, sender: MessageSender) {
+    while volumes.changed().await.is_ok() {
+        let volume = *volumes.borrow_and_update();
+        if let Some(volume) = volume {
+            sender.action(Action::Volume(volume));
+        }
+        time::sleep(VOLUME_INTERVAL).await;
+    }
 }
 
 fn map_uri(uri: &str) -> String {
@@ -4,19 +4,26 @@ use anyhow::Result;
 use async_trait::async_trait;
 use futures::future::join_all;
 use log::{error, debug};
-use souvlaki::MediaPlayback;
 use tokio::sync::broadcast::Receiver;
 
-use crate::{messages::Command, config::Config};
+use crate::{messages::Command, config::Config, media::MediaPlayback, lyrics::Lyrics};
 
+pub mod lyrics;
 pub mod media_controls;
+pub mod podcasts;
 pub mod rpc;
 
 #[async_trait]
 pub trait Listener {
     async fn handle(&mut self, command: Command, config: &Config) -> Result<()>;
 
+    /// Called once before the listener starts recieving commands
+    async fn start(&mut self, _config: &Config) -> Result<()> { Ok(()) }
+
     async fn listen(mut self: Box<Self>, mut reciever: Receiver<Command>, config: &Config) {
+        self.start(config).await
+            .unwrap_or_else(|err| error!("{} failed to start: {err:?}", self.name()));
+
         while let Ok(command) = reciever.recv().await {
             if matches!(command, Command::Exit) {
                 debug!("{} exited", self.name());
@@ -65,6 +72,15 @@ impl Listener for Logger {
             Command::Attached(true) => debug!("attaching..."),
             Command::Attached(false) => debug!("detaching..."),
             Command::Volume(vol) => debug!("updating volume: {vol}"),
+            Command::Settings(settings) => debug!("updating settings: {settings:?}"),
+            Command::OutputDevices(devices) => debug!("updating output devices: {devices:?}"),
+            Command::Queue(queue) => debug!("updating queue: {} tracks", queue.tracks.len()),
+            Command::Playlists(playlists) => debug!("updating playlists: {} playlists", playlists.len()),
+            Command::Lyrics(lyrics) => debug!("updating lyrics: {}", match lyrics.as_deref() {
+                Some(Lyrics::Synced(lines)) => format!("{} synced lines", lines.len()),
+                Some(Lyrics::Plain(_)) => "not synced".to_owned(),
+                None => "none".to_owned(),
+            }),
             _ => (),
         }
         Ok(())
@@ -0,0 +1,177 @@
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+use anyhow::{Result, Context};
+use async_trait::async_trait;
+use log::*;
+use tokio::fs;
+
+use crate::{config::Config, messages::{MessageSender, Command}, communication::Action, clock::Clock, media::MediaPlayback};
+
+use super::Listener;
+
+/// Episodes this close to the end count as finished, so they start over next time
+const FINISHED: Duration = Duration::from_secs(30);
+/// Positions this close to the start aren't worth going back to,
+/// and they're also where an episode is before it's been resumed
+const MINIMUM: Duration = Duration::from_secs(10);
+
+/// Where each podcast episode was left off, in milliseconds by its path
+#[derive(Debug, Default)]
+struct Positions(HashMap<String, u64>);
+
+impl Positions {
+    /// Remembers where an episode was left, returning whether anything changed
+    fn leave(&mut self, path: &str, position: Duration, duration: Option<Duration>) -> bool {
+        if duration.is_some_and(|duration| position + FINISHED >= duration) {
+            return self.0.remove(path).is_some();
+        }
+        if position < MINIMUM { return false; }
+
+        let millis = u64::try_from(position.as_millis()).unwrap_or(u64::MAX);
+        self.0.insert(path.to_owned(), millis) != Some(millis)
+    }
+
+    /// Where the episode should start from, if it was left partway through
+    fn resume(&self, path: &str) -> Option<Duration> {
+        self.0.get(path).copied().map(Duration::from_millis)
+    }
+}
+
+/// Remembers where podcast episodes were left off, and seeks back there when they're played again
+///
+/// MusicBee saves the positions too, but the plugin can't see them
+pub struct Resumer {
+    sender: MessageSender,
+    file: Option<PathBuf>,
+    positions: Positions,
+    /// The path and length of the episode that's playing
+    episode: Option<(String, Option<Duration>)>,
+    /// The clock as of the last update, which keeps counting once the track has changed
+    clock: Clock,
+}
+
+#[async_trait]
+impl Listener for Resumer {
+    async fn start(&mut self, _: &Config) -> Result<()> {
+        let Some(file) = &self.file else { return Ok(()) };
+        if !file.exists() { return Ok(()); }
+
+        let contents = fs::read_to_string(file).await
+            .context("failed to read the podcast positions")?;
+        self.positions.0 = serde_json::from_str(&contents)
+            .context("failed to parse the podcast positions")?;
+        Ok(())
+    }
+
+    async fn handle(&mut self, command: Command, _: &Config) -> Result<()> {
+        match command {
+            Command::Metadata(metadata) => {
+                // ratings and loves rewrite the metadata of the same episode
+                if metadata.path.is_some() && metadata.path.as_ref() == self.episode.as_ref().map(|(path, _)| path) {
+                    return Ok(());
+                }
+                self.leave().await?;
+
+                self.episode = metadata.path.clone()
+                    .filter(|_| metadata.podcast.is_some())
+                    .map(|path| (path, metadata.duration));
+                self.clock = *self.sender.clock().lock();
+
+                if let Some(position) = self.episode.as_ref().and_then(|(path, _)| self.positions.resume(path)) {
+                    debug!("resuming the episode at {position:?}");
+                    self.sender.action(Action::Position(position));
+                }
+            },
+            // stopping resets the clock, so where it was is only known from before
+            Command::Playback(playback) if matches!(*playback, MediaPlayback::Stopped) => {
+                self.leave().await?;
+                self.clock = *self.sender.clock().lock();
+            },
+            Command::Playback(_) | Command::Seeked(_) => {
+                self.clock = *self.sender.clock().lock();
+                if !self.clock.playing() {
+                    self.leave().await?;
+                }
+            },
+            // the daemon is exiting, or the player went away
+            Command::Attached(false) => self.leave().await?,
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str { "podcasts" }
+}
+
+impl Resumer {
+    pub fn new(sender: MessageSender, config: &Config) -> Self {
+        let name = match &config.instance {
+            Some(instance) => format!("podcast-positions-{instance}.json"),
+            None => "podcast-positions.json".to_owned(),
+        };
+        let file = crate::project_dirs().map(|directories| directories.data_dir().join(name));
+        if file.is_none() {
+            warn!("there's no data directory, so podcast positions won't be kept between runs");
+        }
+
+        Self { sender, file, positions: Positions::default(), episode: None, clock: Clock::default() }
+    }
+
+    /// Remembers where the playing episode is, saving it if it changed
+    async fn leave(&mut self) -> Result<()> {
+        let Some((path, duration)) = &self.episode else { return Ok(()) };
+        if !self.positions.leave(path, self.clock.position(), *duration) {
+            return Ok(());
+        }
+
+        let Some(file) = &self.file else { return Ok(()) };
+        if let Some(directory) = file.parent() {
+            fs::create_dir_all(directory).await
+                .context("failed to create the data directory")?;
+        }
+        let contents = serde_json::to_string(&self.positions.0)
+            .context("failed to serialize the podcast positions")?;
+        fs::write(file, contents).await
+            .context("failed to save the podcast positions")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    #[test]
+    fn positions() {
+        let mut positions = Positions::default();
+        assert!(positions.leave("episode", Duration::from_secs(600), Some(HOUR)));
+        assert_eq!(positions.resume("episode"), Some(Duration::from_secs(600)));
+        // leaving it at the same place again doesn't need saving
+        assert!(!positions.leave("episode", Duration::from_secs(600), Some(HOUR)));
+        assert_eq!(positions.resume("another episode"), None);
+
+        // lengths aren't always known
+        assert!(positions.leave("stream", Duration::from_secs(600), None));
+        assert_eq!(positions.resume("stream"), Some(Duration::from_secs(600)));
+    }
+
+    #[test]
+    fn starting_over() {
+        let mut positions = Positions::default();
+        positions.leave("episode", Duration::from_secs(600), Some(HOUR));
+
+        // finished episodes start from the beginning
+        assert!(positions.leave("episode", HOUR - Duration::from_secs(5), Some(HOUR)));
+        assert_eq!(positions.resume("episode"), None);
+
+        // episodes that were barely started aren't remembered
+        assert!(!positions.leave("episode", Duration::from_secs(3), Some(HOUR)));
+        assert_eq!(positions.resume("episode"), None);
+
+        // and neither are episodes that haven't been resumed yet
+        positions.leave("episode", Duration::from_secs(600), Some(HOUR));
+        assert!(!positions.leave("episode", Duration::ZERO, Some(HOUR)));
+        assert_eq!(positions.resume("episode"), Some(Duration::from_secs(600)));
+    }
+}
@@ -1,21 +1,20 @@
 #![allow(dead_code)]
 
-use std::{sync::Arc, collections::HashMap, path::{Path, PathBuf}, time::{Instant, Duration}};
+use std::{sync::Arc, collections::HashMap, path::{Path, PathBuf}, time::{Instant, Duration, SystemTime, UNIX_EPOCH}};
 
 use anyhow::{Result, anyhow, Context, bail};
 use async_trait::async_trait;
-use discord_rich_presence::{DiscordIpcClient, DiscordIpc, activity::{Activity, Assets}};
+use discord_rich_presence::{DiscordIpcClient, DiscordIpc, activity::{Activity, Assets, Timestamps}};
 use futures::future::join_all;
 use log::trace;
 use reqwest::{multipart::{Form, Part}, Client, Body};
 use serde::{Serialize, Deserialize};
 use serde_json::Value;
-use souvlaki::MediaMetadata;
 use tokio::fs::File;
 use tokio_util::io::ReaderStream;
 use url::Url;
 
-use crate::{config::Config, messages::Command};
+use crate::{config::Config, messages::Command, media::Metadata, clock::SharedClock};
 
 use super::Listener;
 
@@ -23,6 +22,10 @@ pub struct Rpc {
     client: DiscordIpcClient,
     cover_cache: CoverCache,
     config: Arc<Config>,
+    clock: SharedClock,
+    metadata: Option<Arc<Metadata>>,
+    /// The lyrics line that's shown, when lyrics are turned on
+    line: Option<Arc<str>>,
     attached: bool,
 }
 
@@ -30,8 +33,17 @@ pub struct Rpc {
 impl Listener for Rpc {
     async fn handle(&mut self, command: Command, _: &Config) -> Result<()> {
         match command {
-            Command::Metadata(metadata) => 
-                self.metadata(&(*metadata).as_ref()).await.context("failed to set metadata")?, 
+            Command::Metadata(metadata) => {
+                self.metadata = Some(metadata);
+                self.refresh().await.context("failed to set metadata")?;
+            },
+            // the timestamps have to follow the position
+            Command::Playback(_) | Command::Seeked(_) =>
+                self.refresh().await.context("failed to set timestamps")?,
+            Command::LyricsLine(line) if self.config.rpc.lyrics && line != self.line => {
+                self.line = line;
+                self.refresh().await.context("failed to set lyrics")?;
+            },
             Command::Attached(true) if !self.attached =>
                 self.attach().context("failed to attach")?,
             Command::Attached(false) if self.attached => 
@@ -46,7 +58,7 @@ impl Listener for Rpc {
 }
 
 impl Rpc {
-    pub fn new(config: Arc<Config>) -> Self {
+    pub fn new(config: Arc<Config>, clock: SharedClock) -> Self {
         // create a client
         // the error type of this is weird (can't be anyhow'd),            
         // and i'm not sure how it can fail, so just expect it
@@ -55,13 +67,15 @@ impl Rpc {
 
         let cover_cache = CoverCache::with(&config.rpc.service);
 
-        Self { client, config, cover_cache, attached: false }
+        Self { client, config, clock, cover_cache, metadata: None, line: None, attached: false }
     }
 
-    async fn metadata(&mut self, metadata: &MediaMetadata<'_>) -> Result<()> {
+    /// Sets the activity to the current track
+    async fn refresh(&mut self) -> Result<()> {
         if !self.attached { return Ok(()); }
+        let Some(metadata) = self.metadata.clone() else { return Ok(()) };
 
-        let MediaMetadata { title, album, artist, cover_url, .. } = metadata;
+        let Metadata { cover_url, duration, .. } = &*metadata;
 
         let large_image = if let Some(cover_url) = cover_url {
             self.cover_cache.resolve_str(cover_url).await?.to_string()
@@ -70,18 +84,63 @@ impl Rpc {
             "https://www.getmusicbee.com/img/musicbee.png".to_string()
         };
 
-        let details = format!("{} - {}", artist.unwrap_or_default(), album.unwrap_or_default());
-        let activity = Activity::new()
-            .state(title.unwrap_or_default())
+        let (state, details) = self.lines(&metadata);
+        let mut activity = Activity::new()
+            .state(&state)
             .details(&details)
             .assets(Assets::new().large_image(&large_image));
 
+        if let Some(timestamps) = self.timestamps(*duration) {
+            activity = activity.timestamps(timestamps);
+        }
+
         self.client.set_activity(activity)
             .map_err(|err| anyhow!("failed to set rpc activity: {err}"))?;
 
         Ok(())
     }
 
+    /// The bottom and top lines of the activity
+    fn lines(&self, metadata: &Metadata) -> (String, String) {
+        let Metadata { title, album, artist, station, stream, podcast, .. } = metadata;
+        let artist = artist.as_deref().unwrap_or_default();
+        let title = title.as_deref().unwrap_or_default();
+
+        // the station or show goes on top, with what's playing under it
+        if let (true, Some(station)) = (*stream, station) {
+            return match artist {
+                "" => (title.to_owned(), station.clone()),
+                artist => (format!("{artist} - {title}"), station.clone()),
+            };
+        }
+        if let Some(podcast) = podcast {
+            return (title.to_owned(), podcast.clone());
+        }
+
+        match self.line.as_deref() {
+            // the line takes the title's place, so the title moves up next to the artist
+            Some(line) if !line.is_empty() => (line.to_owned(), format!("{artist} - {title}")),
+            _ => (title.to_owned(), format!("{artist} - {}", album.as_deref().unwrap_or_default())),
+        }
+    }
+
+    /// When the track started and when it'll end, only while it's playing
+    ///
+    /// Streams don't have a duration, so they only show how long they've been playing
+    fn timestamps(&self, duration: Option<Duration>) -> Option<Timestamps> {
+        let clock = *self.clock.lock();
+        if !clock.playing() { return None; }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+        let start = now.checked_sub(clock.position())?;
+
+        let timestamps = Timestamps::new().start(start.as_secs().try_into().ok()?);
+        Some(match duration {
+            Some(duration) => timestamps.end((start + duration).as_secs().try_into().ok()?),
+            None => timestamps,
+        })
+    }
+
     fn attach(&mut self) -> Result<()> {
         if !self.attached {
             self.client.connect()
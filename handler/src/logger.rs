@@ -8,7 +8,7 @@ use crate::config::Config;
 const FILE: &str = "handler.log";
 
 pub fn init(config: &Config) -> io::Result<()> {
-    let dir = &config.communication.directory;
+    let dir = config.communication.directory.get();
     let target = Box::new(File::create(format!("{dir}/{FILE}"))?);
 
     env_logger::Builder::new()
@@ -20,7 +20,7 @@ pub fn init(config: &Config) -> io::Result<()> {
 }
 
 pub fn open(config: &Config) {
-    let dir = &config.communication.directory;
+    let dir = config.communication.directory.get();
     open::that(format!("{dir}/{FILE}"))
         .unwrap_or_else(|err| error!("failed to open {dir}/{FILE}: {err}"));
 }
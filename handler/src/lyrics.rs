@@ -0,0 +1,304 @@
+use std::{path::Path, time::{Duration, SystemTime}};
+
+use anyhow::{Result, Context};
+use tokio::time;
+
+use crate::{config::Config, clock::Clock, filesystem::{self, LYRICS_FILE, PLAYBACK_FILE}, media::{MediaPlayback, MediaPosition}};
+
+/// How often `lyrics --follow` checks whether the plugin sent anything new
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The lyrics of the playing track
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lyrics {
+    Plain(String),
+    /// Lines with when they start, in order
+    Synced(Vec<Line>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub time: Duration,
+    pub text: String,
+}
+
+impl Lyrics {
+    /// Parses lyrics, which are synced when they have LRC timestamps
+    ///
+    /// Returns None when there aren't any lyrics
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        if text.is_empty() { return None; }
+
+        let mut offset = 0;
+        let mut lines = Vec::new();
+        for line in text.lines() {
+            let (times, text) = parse_tags(line, &mut offset);
+            lines.extend(times.into_iter().map(|time| (time, text)));
+        }
+
+        if lines.is_empty() {
+            return Some(Self::Plain(text.to_owned()));
+        }
+
+        // a positive offset shows the lines sooner
+        let mut lines: Vec<_> = lines.into_iter()
+            .map(|(time, text)| Line {
+                time: Duration::from_millis(time.saturating_add_signed(offset.saturating_neg())),
+                text: text.trim().to_owned(),
+            })
+            .collect();
+        // lines with more than one timestamp are repeated across the song
+        lines.sort_by_key(|line| line.time);
+        Some(Self::Synced(lines))
+    }
+
+    /// The lyrics without any timestamps
+    pub fn text(&self) -> String {
+        match self {
+            Self::Plain(text) => text.clone(),
+            Self::Synced(lines) => lines.iter()
+                .map(|line| line.text.as_str())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+
+    /// The index of the synced line that's being sung at the position
+    pub fn line_at(&self, position: Duration) -> Option<usize> {
+        let Self::Synced(lines) = self else { return None };
+        lines.partition_point(|line| line.time <= position).checked_sub(1)
+    }
+
+    /// When the synced line after the position starts
+    pub fn next_line_after(&self, position: Duration) -> Option<Duration> {
+        let Self::Synced(lines) = self else { return None };
+        lines.get(lines.partition_point(|line| line.time <= position)).map(|line| line.time)
+    }
+
+    pub fn line(&self, index: usize) -> Option<&str> {
+        let Self::Synced(lines) = self else { return None };
+        lines.get(index).map(|line| line.text.as_str())
+    }
+}
+
+/// Splits the tags at the start of an LRC line from its text
+///
+/// Returns the times the line is sung in milliseconds, and sets the offset if the line has one.
+/// Other tags, like `[ar:...]`, are skipped
+fn parse_tags<'a>(mut line: &'a str, offset: &mut i64) -> (Vec<u64>, &'a str) {
+    let mut times = Vec::new();
+    while let Some((tag, rest)) = line.trim_start().strip_prefix('[').and_then(|line| line.split_once(']')) {
+        if let Some(time) = parse_time(tag) {
+            times.push(time);
+        } else if let Some(value) = tag.strip_prefix("offset:") {
+            *offset = value.trim().parse().unwrap_or(*offset);
+        } else if !tag.contains(':') {
+            // not a tag, just text in brackets
+            break;
+        }
+        line = rest;
+    }
+    (times, line)
+}
+
+/// Parses an LRC timestamp, like `01:23.45`, into milliseconds
+fn parse_time(time: &str) -> Option<u64> {
+    let (minutes, seconds) = time.split_once(':')?;
+    // some files seperate the hundredths with another colon
+    let (seconds, fraction) = seconds.split_once(['.', ':']).unwrap_or((seconds, "0"));
+
+    let all_digits = |part: &str| !part.is_empty() && part.bytes().all(|byte| byte.is_ascii_digit());
+    if ![minutes, seconds, fraction].into_iter().all(all_digits) { return None; }
+
+    // the fraction can have any precision, but only milliseconds matter
+    let fraction = format!("{fraction:0<3}");
+    let millis: u64 = fraction[..3].parse().ok()?;
+
+    Some((minutes.parse::<u64>().ok()? * 60 + seconds.parse::<u64>().ok()?) * 1000 + millis)
+}
+
+/// Reads the lyrics file that the plugin sent
+///
+/// The first line is the playing file, and an `.lrc` file next to it is used over
+/// what MusicBee has
+pub fn load(contents: &str, config: &Config) -> Option<Lyrics> {
+    let (file, lyrics) = contents.split_once('\n').unwrap_or((contents, ""));
+    let file = file.trim_end_matches('\r');
+
+    if !file.is_empty() {
+        let lrc = Path::new(&config.map_filename(file)).with_extension("lrc");
+        if let Ok(lyrics) = std::fs::read_to_string(lrc) {
+            return Lyrics::parse(&lyrics);
+        }
+    }
+
+    Lyrics::parse(lyrics)
+}
+
+fn read_lyrics(config: &Config) -> Result<Option<Lyrics>> {
+    let contents = std::fs::read_to_string(config.get_comm_path(LYRICS_FILE))
+        .context("failed to read the lyrics")?;
+    Ok(load(&contents, config))
+}
+
+/// Prints the lyrics that the plugin last sent
+pub fn print(config: &Config) -> Result<()> {
+    match read_lyrics(config)? {
+        Some(lyrics) => println!("{}", lyrics.text()),
+        None => println!("No lyrics"),
+    }
+    Ok(())
+}
+
+/// Prints each synced line as it's sung, or the whole lyrics when they aren't synced
+///
+/// This runs until it's interrupted
+pub async fn follow(config: &Config) -> Result<()> {
+    let lyrics_path = config.get_comm_path(LYRICS_FILE);
+    let playback_path = config.get_comm_path(PLAYBACK_FILE);
+
+    let mut lyrics_modified = None;
+    let mut playback_modified = None;
+    let mut lyrics = None;
+    let mut clock = Clock::default();
+    // the line that was printed last, Some(None) before the first line of the song
+    let mut shown: Option<Option<usize>> = None;
+
+    loop {
+        let changed = modified(&lyrics_path);
+        if changed != lyrics_modified {
+            lyrics_modified = changed;
+            lyrics = read_lyrics(config)?;
+            shown = None;
+
+            if let Some(Lyrics::Plain(text)) = &lyrics {
+                println!("{text}\n");
+            }
+        }
+
+        let changed = modified(&playback_path);
+        if changed != playback_modified {
+            playback_modified = changed;
+            let contents = std::fs::read_to_string(&playback_path)
+                .context("failed to read the playback")?;
+            if let Some((playback, _)) = filesystem::parse_playback_contents(&contents)? {
+                clock.update(&since_written(playback, changed));
+            }
+        }
+
+        if let Some(lyrics @ Lyrics::Synced(_)) = &lyrics {
+            let index = lyrics.line_at(clock.position());
+            if shown != Some(index) {
+                shown = Some(index);
+                // nothing is printed before the first line
+                if let Some(line) = index.and_then(|index| lyrics.line(index)) {
+                    println!("{line}");
+                }
+            }
+        }
+
+        time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Moves the playback forward by how long it's been playing since the plugin wrote it
+fn since_written(playback: MediaPlayback, written: Option<SystemTime>) -> MediaPlayback {
+    let elapsed = written
+        .and_then(|written| written.elapsed().ok())
+        .unwrap_or_default();
+
+    match playback {
+        MediaPlayback::Playing { progress: Some(MediaPosition(progress)) } =>
+            MediaPlayback::Playing { progress: Some(MediaPosition(progress + elapsed)) },
+        playback => playback,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn synced(lyrics: &str) -> Vec<(u64, String)> {
+        match Lyrics::parse(lyrics) {
+            Some(Lyrics::Synced(lines)) => lines.into_iter()
+                .map(|line| (u64::try_from(line.time.as_millis()).unwrap(), line.text))
+                .collect(),
+            other => panic!("expected synced lyrics, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(parse_time("01:23.45"), Some(83_450));
+        assert_eq!(parse_time("01:23.4"), Some(83_400));
+        assert_eq!(parse_time("01:23.456"), Some(83_456));
+        assert_eq!(parse_time("01:23.4567"), Some(83_456));
+        // some files seperate the hundredths with a colon
+        assert_eq!(parse_time("01:23:45"), Some(83_450));
+        assert_eq!(parse_time("01:23"), Some(83_000));
+
+        assert_eq!(parse_time("ar:Someone"), None);
+        assert_eq!(parse_time("01:2x.00"), None);
+        assert_eq!(parse_time(":23.00"), None);
+    }
+
+    #[test]
+    fn plain() {
+        assert_eq!(Lyrics::parse("  \n "), None);
+        assert_eq!(Lyrics::parse("first\nsecond\n"), Some(Lyrics::Plain("first\nsecond".to_owned())));
+        // text in brackets isn't a tag
+        assert_eq!(Lyrics::parse("[Chorus]\nla la"), Some(Lyrics::Plain("[Chorus]\nla la".to_owned())));
+    }
+
+    #[test]
+    fn tags() {
+        let lyrics = "[ar:Someone]\n[ti:Something]\n[00:01.00]first\n[00:02.00][Chorus] second";
+        assert_eq!(synced(lyrics), [(1000, "first".to_owned()), (2000, "[Chorus] second".to_owned())]);
+    }
+
+    #[test]
+    fn repeated_lines() {
+        let lyrics = "[00:05.00][00:01.00]chorus\n[00:03.00]verse";
+        assert_eq!(synced(lyrics), [
+            (1000, "chorus".to_owned()),
+            (3000, "verse".to_owned()),
+            (5000, "chorus".to_owned()),
+        ]);
+    }
+
+    #[test]
+    fn offsets() {
+        // a positive offset shows the lines sooner
+        assert_eq!(synced("[offset:+500]\n[00:02.00]line"), [(1500, "line".to_owned())]);
+        assert_eq!(synced("[offset:-500]\n[00:02.00]line"), [(2500, "line".to_owned())]);
+        // lines can't start before the track does
+        assert_eq!(synced("[offset:3000]\n[00:02.00]line"), [(0, "line".to_owned())]);
+        // broken offsets are ignored
+        assert_eq!(synced("[offset:soon]\n[00:02.00]line"), [(2000, "line".to_owned())]);
+    }
+
+    #[test]
+    fn following() {
+        let lyrics = Lyrics::parse("[00:01.00]first\n[00:03.00]second").unwrap();
+
+        // nothing is being sung before the first line
+        assert_eq!(lyrics.line_at(Duration::ZERO), None);
+        assert_eq!(lyrics.next_line_after(Duration::ZERO), Some(Duration::from_secs(1)));
+
+        assert_eq!(lyrics.line_at(Duration::from_secs(1)), Some(0));
+        assert_eq!(lyrics.line_at(Duration::from_secs(2)).and_then(|index| lyrics.line(index)), Some("first"));
+        assert_eq!(lyrics.next_line_after(Duration::from_secs(2)), Some(Duration::from_secs(3)));
+
+        assert_eq!(lyrics.line_at(Duration::from_secs(60)), Some(1));
+        assert_eq!(lyrics.next_line_after(Duration::from_secs(60)), None);
+
+        let plain = Lyrics::parse("just words").unwrap();
+        assert_eq!(plain.line_at(Duration::from_secs(1)), None);
+        assert_eq!(plain.text(), "just words");
+    }
+}
@@ -6,6 +6,7 @@
 
 mod tray;
 mod filesystem;
+mod transport;
 mod config;
 mod logger;
 mod communication;
@@ -13,8 +14,19 @@ mod cli;
 mod daemon;
 mod listener;
 mod messages;
+mod doctor;
+mod bindings;
+mod media;
+mod clock;
+mod ctl;
+mod queue;
+mod playlists;
+mod search;
+mod lyrics;
+mod podcasts;
+mod mpris;
 
-use std::time::Duration;
+use std::{time::Duration, sync::{Mutex, MutexGuard, PoisonError}};
 
 use clap::Parser;
 use cli::{Cli, Commands};
@@ -27,6 +39,13 @@ use log::*;
 use anyhow::*;
 use tokio::runtime::Runtime;
 
+/// Locks the mutex, even if something panicked while holding it
+///
+/// Shared state is always left valid between changes, so a poisoned lock is still usable
+fn lock_unpoisoned<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
 #[must_use]
 fn project_dirs() -> Option<ProjectDirs> {
     ProjectDirs::from("com.github", "baanan", "Musicbee Mediakeys")
@@ -35,10 +54,13 @@ fn project_dirs() -> Option<ProjectDirs> {
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
-    let (config, config_err) = config::get_or_save_default(&cli.config_path);
+    let (config, config_err) = config::get_or_save_default(&cli.config_path, cli.instance.as_deref());
 
-    filesystem::create_file_structure(&config)
-        .context("failed to create the communication file structure")?;
+    // the doctor explains what's wrong with the file structure instead
+    if !matches!(cli.command, Commands::Doctor) {
+        filesystem::create_file_structure(&config)
+            .context("failed to create the communication file structure")?;
+    }
 
     match cli.command {
         Commands::Run { run_config } => daemon::run(config, &run_config, config_err)?,
@@ -48,13 +70,23 @@ fn main() -> Result<()> {
             print!("{}", cli.config_file().display()),
         Commands::ConfigFile { open: true } => 
             open::that(cli.config_file()).context("failed to open config file")?,
+        Commands::Doctor => 
+            doctor::run(&config, &cli.config_file(), config_err.as_ref()),
+        Commands::Ctl { control } => ctl::run(&config, &control)?,
+        Commands::Queue { command: None } => queue::print(&config)?,
+        Commands::Queue { command: Some(command) } => queue::run(&config, &command)?,
+        Commands::Playlists { command } => playlists::run(&config, &command)?,
+        Commands::Search { search } => search::run(&config, &search)?,
+        Commands::Podcasts { command } => podcasts::run(&config, &command)?,
+        Commands::Lyrics { follow: false } => lyrics::print(&config)?,
+        Commands::Lyrics { follow: true } => run_async(lyrics::follow(&config))?,
     }
 
     Ok(())
 }
 
 // async is run later in daemon::run because daemonize breaks async
-fn run_async(function: impl Future<Output = Result<()>>) -> Result<()> {
+fn run_async<T>(function: impl Future<Output = Result<T>>) -> Result<T> {
     let rt = Runtime::new().context("failed to initialize async runtime")?;
     let res = rt.block_on(function);
     rt.shutdown_timeout(Duration::from_secs(1));
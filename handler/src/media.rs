@@ -0,0 +1,141 @@
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::communication::RepeatMode;
+
+/// The track that's currently playing
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub album: Option<String>,
+    /// The artist as MusicBee displays it
+    pub artist: Option<String>,
+    pub album_artist: Option<String>,
+    /// Every artist of the track, split up
+    pub artists: Vec<String>,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub genres: Vec<String>,
+    /// The mapped path of the track
+    pub path: Option<String>,
+    pub cover_url: Option<String>,
+    pub duration: Option<Duration>,
+    /// Out of 100, so that half stars fit
+    pub rating: Option<u8>,
+    pub loved: bool,
+    /// The name of the internet radio station, when it's known
+    pub station: Option<String>,
+    /// Whether the track is a stream, which can't be seeked and doesn't end
+    pub stream: bool,
+    /// The show, when the track is a podcast episode
+    pub podcast: Option<String>,
+    /// The episode's description, for podcasts
+    pub description: Option<String>,
+}
+
+/// A window of the now playing list around the playing track
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Queue {
+    /// The index of the playing track in the now playing list
+    pub current: Option<usize>,
+    pub tracks: Vec<QueueTrack>,
+}
+
+impl Queue {
+    pub fn current_track(&self) -> Option<&QueueTrack> {
+        self.tracks.iter().find(|track| Some(track.index) == self.current)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct QueueTrack {
+    /// Where the track is in the now playing list
+    pub index: usize,
+    /// Only the title, album, artists and path are known
+    pub metadata: Metadata,
+}
+
+/// A track found by searching the library
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+pub struct SearchResult {
+    /// The file as MusicBee knows it, which it can play
+    pub file: String,
+    /// The mapped path of the file
+    pub path: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub track_number: Option<u32>,
+    pub year: Option<String>,
+    pub genres: Vec<String>,
+}
+
+/// A podcast that MusicBee is subscribed to
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+pub struct Subscription {
+    pub id: String,
+    pub title: String,
+    /// Only the episodes that haven't been played
+    pub episodes: Vec<Episode>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+pub struct Episode {
+    /// Where the episode is, which MusicBee can play
+    pub url: String,
+    pub title: Option<String>,
+    /// When the episode came out, as MusicBee shows it
+    pub date: Option<String>,
+    /// How long the episode is, as MusicBee shows it
+    pub duration: Option<String>,
+    pub downloaded: bool,
+}
+
+/// One of MusicBee's playlists
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Playlist {
+    /// Where MusicBee keeps the playlist, which is also how it's played
+    pub url: String,
+    pub name: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MediaPosition(pub Duration);
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MediaPlayback {
+    Stopped,
+    Paused { progress: Option<MediaPosition> },
+    Playing { progress: Option<MediaPosition> },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SeekDirection {
+    Forward,
+    Backward,
+}
+
+/// Events recieved from the media controls
+#[derive(Clone, PartialEq, Debug)]
+pub enum MediaControlEvent {
+    Play,
+    Pause,
+    Toggle,
+    Next,
+    Previous,
+    Stop,
+    SeekBy(SeekDirection, Duration),
+    SetPosition(MediaPosition),
+    SetVolume(f64),
+    SetShuffle(bool),
+    SetRepeat(RepeatMode),
+    OpenUri(String),
+    /// Plays the track at this index of the now playing list
+    GoTo(usize),
+    /// Plays the playlist with this url
+    PlayPlaylist(String),
+    Raise,
+    Quit,
+}
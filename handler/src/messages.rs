@@ -1,30 +1,75 @@
-use std::sync::Arc;
-use std::time::Duration;
+use std::{sync::{Arc, Mutex, MutexGuard}, time::Duration};
 
-use souvlaki::{MediaMetadata, MediaPlayback, MediaControlEvent};
-use tokio::sync::broadcast::{self, Sender, Receiver};
+use tokio::{sync::broadcast::{self, Sender, Receiver}, time};
 
-use crate::{listener::List, config::Config};
+use crate::{listener::List, config::Config, communication::{Action, RepeatMode, ReplayGainMode}, media::{Metadata, MediaPlayback, MediaControlEvent, Queue, Playlist}, clock::SharedClock, lyrics::Lyrics};
 
 #[derive(Debug, Clone)]
 pub enum Command {
     Exit,
     Playback(Arc<MediaPlayback>),
-    Metadata(Arc<OwnedMetadata>),
+    /// The position jumped away from where the clock thought it would be
+    Seeked(Duration),
+    Settings(Arc<Settings>),
+    OutputDevices(Arc<OutputDevices>),
+    Queue(Arc<Queue>),
+    Playlists(Arc<Vec<Playlist>>),
+    Lyrics(Option<Arc<Lyrics>>),
+    /// The synced line that's being sung, if there is one
+    LyricsLine(Option<Arc<str>>),
+    Metadata(Arc<Metadata>),
     Volume(f64),
     Attached(bool),
     Update,
     UpdatePlayback,
     UpdateMetadata,
     UpdateVolume,
+    UpdateOutputDevices,
+    UpdateQueue,
+    UpdatePlaylists,
+    UpdateLyrics,
     UpdatePluginActivation,
     MediaControlEvent(Arc<MediaControlEvent>),
+    Action(Arc<Action>),
 }
 
 #[derive(Clone)]
 pub struct MessageSender {
     tx: Sender<Command>,
     config: Arc<Config>,
+    clock: SharedClock,
+    attachment: Arc<Mutex<Attachment>>,
+    /// The path of the playing track, to tell track changes from rewrites of the same track
+    track: Arc<Mutex<Option<String>>>,
+}
+
+/// Why the listeners were last attached or detached
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttachReason {
+    /// From the tray or the command line
+    User,
+    /// The plugin was activated or deactivated
+    Plugin,
+    /// MusicBee started or stopped playing, with `detach_on_stop`
+    Playback,
+}
+
+/// Whether the listeners are attached, and why
+#[derive(Debug)]
+struct Attachment {
+    attached: bool,
+    reason: AttachReason,
+    /// The stop that's waiting out the grace period before it detaches
+    pending_stop: Option<u64>,
+    /// How many stops there have been, to tell them apart
+    stops: u64,
+}
+
+impl Default for Attachment {
+    fn default() -> Self {
+        // nothing is playing yet, so playing attaches like it would after a stop
+        Self { attached: false, reason: AttachReason::Playback, pending_stop: None, stops: 0 }
+    }
 }
 
 impl MessageSender {
@@ -45,19 +90,54 @@ impl MessageSender {
     pub fn playback(&self, playback: MediaPlayback) {
         if self.config.detach_on_stop {
             match playback {
-                MediaPlayback::Stopped => self.detach(),
-                // the attach can't also update, or it could create an infinite loop of
-                // checking the playback and updating
-                // FIX: the plugin doesn't know whether the listeners detached due to a
-                // stop or due to user input. This could lead to unwanted attaches.
-                _ => self.attach_without_update(),
+                MediaPlayback::Stopped => self.stopped(),
+                _ => self.playing(),
             }
         }
-        self.send(Command::Playback(Arc::new(playback)))
+        let seeked = self.clock.lock().update(&playback);
+        self.send(Command::Playback(Arc::new(playback)));
+        if let Some(position) = seeked {
+            self.send(Command::Seeked(position));
+        }
+    }
+
+    /// Recieves every command sent from now on
+    #[cfg(test)]
+    pub fn subscribe(&self) -> Receiver<Command> { self.tx.subscribe() }
+
+    /// The clock that follows the position of the current track
+    pub const fn clock(&self) -> &SharedClock { &self.clock }
+
+    pub fn settings(&self, settings: Settings) {
+        self.send(Command::Settings(Arc::new(settings)))
+    }
+
+    pub fn output_devices(&self, output_devices: OutputDevices) {
+        self.send(Command::OutputDevices(Arc::new(output_devices)))
+    }
+
+    pub fn queue(&self, queue: Queue) {
+        self.send(Command::Queue(Arc::new(queue)))
     }
 
-    pub fn metadata(&self, metadata: MediaMetadata<'_>) {
-        self.send(Command::Metadata(Arc::new(metadata.into())))
+    pub fn playlists(&self, playlists: Vec<Playlist>) {
+        self.send(Command::Playlists(Arc::new(playlists)))
+    }
+
+    pub fn lyrics(&self, lyrics: Option<Lyrics>) {
+        self.send(Command::Lyrics(lyrics.map(Arc::new)))
+    }
+
+    pub fn lyrics_line(&self, line: Option<&str>) {
+        self.send(Command::LyricsLine(line.map(Arc::from)))
+    }
+
+    pub fn metadata(&self, metadata: Metadata) {
+        // ratings, loves and stream titles rewrite the metadata without changing the track
+        if self.track_changed(metadata.path.as_deref()) {
+            self.clock.lock().restart();
+        }
+        self.send(Command::Metadata(Arc::new(metadata)))
     }
 
     pub fn volume(&self, volume: f64) {
@@ -68,11 +148,17 @@ impl MessageSender {
         if !activated && self.config.exit_with_plugin {
             self.exit()
         } else {
-            self.attach_as(activated)
+            self.attach_as(activated, AttachReason::Plugin)
         }
     }
 
-    pub fn attach_as(&self, attached: bool) {
+    pub fn attach_as(&self, attached: bool, reason: AttachReason) {
+        {
+            let mut attachment = self.attachment();
+            attachment.attached = attached;
+            attachment.reason = reason;
+            attachment.pending_stop = None;
+        }
         self.send(Command::Attached(attached));
         // send an update signal as well 
         // a weird side effect of this is that 
@@ -81,16 +167,74 @@ impl MessageSender {
         if attached { self.update() }
     }
 
-    pub fn attach_without_update(&self) { self.send(Command::Attached(true)); }
-    pub fn attach(&self) { self.attach_as(true) }
-    pub fn detach(&self) { self.attach_as(false) }
+    pub fn attach(&self) { self.attach_as(true, AttachReason::User) }
+    pub fn detach(&self) { self.attach_as(false, AttachReason::User) }
+
+    /// Remembers the playing track, returning whether it's a different one
+    fn track_changed(&self, path: Option<&str>) -> bool {
+        let mut track = crate::lock_unpoisoned(&self.track);
+        if track.as_deref() == path { return false; }
+        *track = path.map(ToOwned::to_owned);
+        true
+    }
+
+    fn attachment(&self) -> MutexGuard<'_, Attachment> {
+        crate::lock_unpoisoned(&self.attachment)
+    }
+
+    /// Detaches once MusicBee has been stopped for the grace period
+    fn stopped(&self) {
+        let stop = {
+            let mut attachment = self.attachment();
+            if !attachment.attached || attachment.pending_stop.is_some() { return; }
+            attachment.stops += 1;
+            attachment.pending_stop = Some(attachment.stops);
+            attachment.stops
+        };
+
+        let sender = self.clone();
+        tokio::spawn(async move {
+            time::sleep(sender.config.detach_grace_period).await;
+            {
+                let mut attachment = sender.attachment();
+                // anything else happening cancels the stop
+                if attachment.pending_stop != Some(stop) { return; }
+                attachment.attached = false;
+                attachment.reason = AttachReason::Playback;
+                attachment.pending_stop = None;
+            }
+            sender.send(Command::Attached(false));
+        });
+    }
+
+    /// Reattaches if stopping was what detached the listeners
+    ///
+    /// Detaches from the user or the plugin stay until they're undone
+    fn playing(&self) {
+        let attach = {
+            let mut attachment = self.attachment();
+            attachment.pending_stop = None;
+            let attach = !attachment.attached && attachment.reason == AttachReason::Playback;
+            attachment.attached |= attach;
+            attach
+        };
+
+        // the attach can't also update, or it could create an infinite loop of
+        // checking the playback and updating
+        if attach { self.send(Command::Attached(true)) }
+    }
 
     pub fn update_metadata(&self) { self.send(Command::UpdateMetadata) }
     pub fn update_playback(&self) { self.send(Command::UpdatePlayback) }
     pub fn update_volume(&self) { self.send(Command::UpdateVolume) }
+    pub fn update_output_devices(&self) { self.send(Command::UpdateOutputDevices) }
+    pub fn update_queue(&self) { self.send(Command::UpdateQueue) }
+    pub fn update_playlists(&self) { self.send(Command::UpdatePlaylists) }
+    pub fn update_lyrics(&self) { self.send(Command::UpdateLyrics) }
     pub fn update_plugin_activation(&self) { self.send(Command::UpdatePluginActivation) }
 
     pub fn media_control_event(&self, event: MediaControlEvent) { self.send(Command::MediaControlEvent(Arc::new(event))) }
+    pub fn action(&self, action: Action) { self.send(Command::Action(Arc::new(action))) }
 }
 
 pub struct Messages { tx: MessageSender, rx: Receiver<Command> }
@@ -103,7 +247,7 @@ impl Messages {
         // that could create a deadlock when the daemon exits,
         // and tries to lock the listeners to detach them
         let (tx, rx) = broadcast::channel(8);
-        Self { tx: MessageSender { tx, config }, rx }
+        Self { tx: MessageSender { tx, config, clock: SharedClock::default(), attachment: Arc::default(), track: Arc::default() }, rx }
     }
 
     /// Returns a [clone](Clone) of the [`MessageSender`]
@@ -115,38 +259,23 @@ impl Messages {
     }
 }
 
-#[derive(Debug)]
-pub struct OwnedMetadata {
-    pub title: Option<String>,
-    pub album: Option<String>,
-    pub artist: Option<String>,
-    pub cover_url: Option<String>,
-    pub duration: Option<Duration>,
-}
-
-impl OwnedMetadata {
-    pub fn as_ref(&self) -> MediaMetadata<'_> {
-        let Self { title, album, artist, cover_url, duration } = self;
-        MediaMetadata {
-            title: title.as_deref(),
-            album: album.as_deref(),
-            artist: artist.as_deref(),
-            cover_url: cover_url.as_deref(),
-            duration: *duration,
-        }
-    }
+/// Player settings reported alongside the playback
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settings {
+    pub shuffle: bool,
+    pub repeat: RepeatMode,
+    pub muted: bool,
+    pub stop_after_current: bool,
+    pub auto_dj: bool,
+    pub equaliser: bool,
+    pub dsp: bool,
+    pub crossfade: bool,
+    pub replay_gain: ReplayGainMode,
 }
 
-// this is ugly because souvlaki turns a MediaMetadata into its own form of OwnedMetadata
-// this means that ToOwned gets called twice, but I can't think of another way to do it
-impl<'a> From<MediaMetadata<'a>> for OwnedMetadata {
-    fn from(MediaMetadata { title, album, artist, cover_url, duration }: MediaMetadata) -> Self {
-        Self {
-            title: title.map(ToOwned::to_owned),
-            album: album.map(ToOwned::to_owned),
-            artist: artist.map(ToOwned::to_owned),
-            cover_url: cover_url.map(ToOwned::to_owned),
-            duration
-        }
-    }
+/// The devices MusicBee can play through
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct OutputDevices {
+    pub active: Option<String>,
+    pub devices: Vec<String>,
 }
@@ -0,0 +1,180 @@
+#![allow(clippy::unused_self)] // the interfaces need self even for constant properties
+
+use std::{sync::{Arc, Mutex, MutexGuard}, time::Duration};
+
+use log::*;
+use zbus::{Connection, ConnectionBuilder, InterfaceRef};
+
+use crate::{config::Config, messages::{MessageSender, Settings}, media::{Metadata, MediaPlayback, Queue, Playlist}, lyrics::Lyrics};
+
+mod player;
+mod playlists;
+mod root;
+mod track_list;
+#[cfg(test)]
+mod tests;
+
+pub use player::Player;
+pub use playlists::Playlists;
+pub use root::Root;
+pub use track_list::TrackList;
+
+/// Where both of the mpris interfaces are served
+pub const PATH: &str = "/org/mpris/MediaPlayer2";
+
+/// Hashes the text into part of an id
+///
+/// This is FNV-1a, since the std hasher can change between rust versions and ids have to stay the same
+fn stable_hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// The state the media controls publish
+#[derive(Debug)]
+pub struct State {
+    pub metadata: Option<Arc<Metadata>>,
+    pub playback: Arc<MediaPlayback>,
+    pub volume: f64,
+    pub settings: Option<Arc<Settings>>,
+    pub queue: Option<Arc<Queue>>,
+    pub playlists: Arc<Vec<Playlist>>,
+    pub lyrics: Option<Arc<Lyrics>>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            metadata: None,
+            playback: Arc::new(MediaPlayback::Stopped),
+            volume: 1.0,
+            settings: None,
+            queue: None,
+            playlists: Arc::default(),
+            lyrics: None,
+        }
+    }
+}
+
+/// [`State`] shared between the listener and the interfaces
+#[derive(Clone, Default)]
+pub struct SharedState(Arc<Mutex<State>>);
+
+impl SharedState {
+    pub fn lock(&self) -> MutexGuard<'_, State> {
+        crate::lock_unpoisoned(&self.0)
+    }
+}
+
+/// An mpris server on the session bus, or any other bus
+///
+/// The server only publishes the name while it's alive, so dropping it removes the player
+pub struct Server {
+    connection: Connection,
+}
+
+impl Server {
+    /// Serves the player on the bus that the builder connects to
+    pub async fn start(
+        builder: ConnectionBuilder<'_>,
+        bus_name: &str,
+        state: SharedState,
+        sender: MessageSender,
+        config: &Config,
+    ) -> zbus::Result<Self> {
+        let root = Root::new(sender.clone(), config);
+        let player = Player::new(state.clone(), sender.clone());
+        let track_list = TrackList::new(state.clone(), sender.clone());
+        let playlists = Playlists::new(state, sender);
+
+        let connection = builder
+            .name(format!("org.mpris.MediaPlayer2.{bus_name}"))?
+            .serve_at(PATH, root)?
+            .serve_at(PATH, player)?
+            .serve_at(PATH, track_list)?
+            .serve_at(PATH, playlists)?
+            .build().await?;
+
+        debug!("serving mpris as {}", connection.unique_name().map_or("(unknown)", |name| name.as_str()));
+
+        Ok(Self { connection })
+    }
+
+    async fn player(&self) -> zbus::Result<InterfaceRef<Player>> {
+        self.connection.object_server().interface::<_, Player>(PATH).await
+    }
+
+    /// Notifies clients that the metadata changed
+    pub async fn metadata_changed(&self) -> zbus::Result<()> {
+        let player = self.player().await?;
+        let context = player.signal_context();
+        let player = player.get().await;
+        player.metadata_changed(context).await?;
+        player.can_seek_changed(context).await
+    }
+
+    /// Notifies clients that the playback status changed
+    pub async fn playback_changed(&self) -> zbus::Result<()> {
+        let player = self.player().await?;
+        let context = player.signal_context();
+        let player = player.get().await;
+        player.playback_status_changed(context).await
+    }
+
+    /// Notifies clients that the position jumped
+    pub async fn seeked(&self, position: Duration) -> zbus::Result<()> {
+        let player = self.player().await?;
+        Player::seeked(player.signal_context(), player::micros(position)).await
+    }
+
+    /// Notifies clients that the volume changed
+    pub async fn volume_changed(&self) -> zbus::Result<()> {
+        let player = self.player().await?;
+        let context = player.signal_context();
+        let player = player.get().await;
+        player.volume_changed(context).await
+    }
+
+    /// Notifies clients that shuffle or repeat changed
+    pub async fn settings_changed(&self) -> zbus::Result<()> {
+        let player = self.player().await?;
+        let context = player.signal_context();
+        let player = player.get().await;
+        player.shuffle_changed(context).await?;
+        player.loop_status_changed(context).await?;
+        // repeating the list changes whether it wraps around
+        player.can_go_next_changed(context).await?;
+        player.can_go_previous_changed(context).await
+    }
+
+    /// Notifies clients that the queue was replaced
+    pub async fn queue_changed(&self, state: &SharedState) -> zbus::Result<()> {
+        let (queue, metadata) = {
+            let state = state.lock();
+            (state.queue.clone(), state.metadata.clone())
+        };
+        let tracks = queue.as_deref()
+            .map(|queue| track_list::queue_ids(queue).into_iter().map(|(id, _)| id).collect())
+            .unwrap_or_default();
+
+        let track_list = self.connection.object_server().interface::<_, TrackList>(PATH).await?;
+        TrackList::track_list_replaced(track_list.signal_context(), tracks, player::track_id(metadata.as_deref())).await?;
+
+        let player = self.player().await?;
+        let context = player.signal_context();
+        let player = player.get().await;
+        player.can_go_next_changed(context).await?;
+        player.can_go_previous_changed(context).await
+    }
+
+    /// Notifies clients that the playlists changed from the old ones
+    pub async fn playlists_changed(&self, old: &[Playlist], new: &[Playlist]) -> zbus::Result<()> {
+        let playlists = self.connection.object_server().interface::<_, Playlists>(PATH).await?;
+        let context = playlists.signal_context();
+        for playlist in playlists::renamed(old, new) {
+            Playlists::playlist_changed(context, playlist).await?;
+        }
+        // clients get the list again when the count changes, which also covers playlists being swapped out
+        let playlists = playlists.get().await;
+        playlists.playlist_count_changed(context).await
+    }
+}
@@ -0,0 +1,254 @@
+use std::{collections::HashMap, time::Duration};
+
+use zbus::{dbus_interface, fdo, SignalContext, zvariant::{ObjectPath, OwnedObjectPath, Value}};
+use url::Url;
+
+use crate::{messages::MessageSender, media::{Metadata, MediaControlEvent, MediaPlayback, SeekDirection, MediaPosition}, communication::RepeatMode, clock::SharedClock};
+
+use super::{SharedState, stable_hash};
+
+const TRACK_PATH: &str = "/com/github/baanan/musicbee_linux/track";
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
+/// The `org.mpris.MediaPlayer2.Player` interface
+pub struct Player {
+    state: SharedState,
+    clock: SharedClock,
+    sender: MessageSender,
+}
+
+impl Player {
+    pub fn new(state: SharedState, sender: MessageSender) -> Self {
+        Self { state, clock: sender.clock().clone(), sender }
+    }
+
+    fn event(&self, event: MediaControlEvent) {
+        self.sender.media_control_event(event);
+    }
+
+    fn track_id(&self) -> OwnedObjectPath {
+        let metadata = self.state.lock().metadata.clone();
+        track_id(metadata.as_deref())
+    }
+
+    /// Streams are live, so they can't be seeked
+    fn seekable(&self) -> bool {
+        self.state.lock().metadata.as_ref().is_some_and(|metadata| !metadata.stream)
+    }
+
+    /// Whether there's a track before or after the playing one in the now playing list
+    ///
+    /// Repeating the whole list always wraps around, and a queue that hasn't been sent yet is assumed to have one
+    fn can_go(&self, forward: bool) -> bool {
+        let state = self.state.lock();
+        let Some(queue) = &state.queue else { return true };
+        let Some(current) = queue.current else { return false };
+
+        let repeating = state.settings.as_ref().is_some_and(|settings| settings.repeat == RepeatMode::All);
+        // the queue is a window around the playing track, so it has the neighbours if there are any
+        repeating || queue.tracks.iter().any(|track| if forward { track.index > current } else { track.index < current })
+    }
+}
+
+/// Creates an id for the track that stays the same between runs
+pub fn track_id(metadata: Option<&Metadata>) -> OwnedObjectPath {
+    let path = match metadata {
+        Some(Metadata { path: Some(path), .. }) => format!("{TRACK_PATH}/{:016x}", stable_hash(path)),
+        // tracks without a path can't be told apart
+        Some(_) => TRACK_PATH.to_owned(),
+        None => NO_TRACK.to_owned(),
+    };
+    OwnedObjectPath::try_from(path).expect("track ids are valid object paths")
+}
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+    fn next(&self) { self.event(MediaControlEvent::Next) }
+    fn previous(&self) { self.event(MediaControlEvent::Previous) }
+    fn pause(&self) { self.event(MediaControlEvent::Pause) }
+    fn play_pause(&self) { self.event(MediaControlEvent::Toggle) }
+    fn stop(&self) { self.event(MediaControlEvent::Stop) }
+    fn play(&self) { self.event(MediaControlEvent::Play) }
+
+    /// Seeks by `offset` microseconds
+    fn seek(&self, offset: i64) {
+        if !self.seekable() { return; }
+        let direction = if offset < 0 { SeekDirection::Backward } else { SeekDirection::Forward };
+        let offset = Duration::from_micros(offset.unsigned_abs());
+        self.event(MediaControlEvent::SeekBy(direction, offset));
+    }
+
+    /// Sets the position to `position` microseconds
+    fn set_position(&self, track_id: ObjectPath<'_>, position: i64) {
+        // the spec says that calls for other tracks are ignored
+        if track_id != *self.track_id() || !self.seekable() { return; }
+        let Ok(position) = u64::try_from(position) else { return };
+        self.event(MediaControlEvent::SetPosition(MediaPosition(Duration::from_micros(position))));
+    }
+
+    fn open_uri(&self, uri: String) {
+        self.event(MediaControlEvent::OpenUri(uri));
+    }
+
+    #[dbus_interface(signal)]
+    pub async fn seeked(context: &SignalContext<'_>, position: i64) -> zbus::Result<()>;
+
+    #[dbus_interface(property)]
+    fn playback_status(&self) -> &str {
+        match *self.state.lock().playback {
+            MediaPlayback::Stopped => "Stopped",
+            MediaPlayback::Paused { .. } => "Paused",
+            MediaPlayback::Playing { .. } => "Playing",
+        }
+    }
+
+    #[dbus_interface(property)]
+    fn loop_status(&self) -> &str {
+        match self.state.lock().settings.as_ref().map(|settings| settings.repeat) {
+            Some(RepeatMode::None) | None => "None",
+            Some(RepeatMode::One) => "Track",
+            Some(RepeatMode::All) => "Playlist",
+        }
+    }
+
+    #[dbus_interface(property)]
+    fn set_loop_status(&self, status: &str) -> zbus::Result<()> {
+        let repeat = match status {
+            "None" => RepeatMode::None,
+            "Track" => RepeatMode::One,
+            "Playlist" => RepeatMode::All,
+            _ => return Err(fdo::Error::InvalidArgs(format!("unknown loop status: {status}")).into()),
+        };
+        self.event(MediaControlEvent::SetRepeat(repeat));
+        Ok(())
+    }
+
+    #[dbus_interface(property)]
+    fn shuffle(&self) -> bool {
+        self.state.lock().settings.as_ref().is_some_and(|settings| settings.shuffle)
+    }
+
+    #[dbus_interface(property)]
+    fn set_shuffle(&self, shuffle: bool) {
+        self.event(MediaControlEvent::SetShuffle(shuffle));
+    }
+
+    /// MusicBee can only play at the normal rate
+    #[dbus_interface(property)]
+    fn rate(&self) -> f64 { 1.0 }
+
+    #[dbus_interface(property)]
+    fn set_rate(&self, _rate: f64) {}
+
+    #[dbus_interface(property)]
+    fn minimum_rate(&self) -> f64 { 1.0 }
+
+    #[dbus_interface(property)]
+    fn maximum_rate(&self) -> f64 { 1.0 }
+
+    #[dbus_interface(property)]
+    fn metadata(&self) -> HashMap<&'static str, Value<'static>> {
+        let (metadata, lyrics) = {
+            let state = self.state.lock();
+            (state.metadata.clone(), state.lyrics.clone())
+        };
+        let mut map = metadata_map(track_id(metadata.as_deref()), metadata.as_deref());
+        if let Some(lyrics) = lyrics {
+            map.insert("xesam:asText", lyrics.text().into());
+        }
+        map
+    }
+
+    #[dbus_interface(property)]
+    fn volume(&self) -> f64 { self.state.lock().volume }
+
+    #[dbus_interface(property)]
+    fn set_volume(&self, volume: f64) {
+        self.event(MediaControlEvent::SetVolume(volume.clamp(0.0, 1.0)));
+    }
+
+    /// The position in microseconds
+    #[dbus_interface(property)]
+    fn position(&self) -> i64 {
+        micros(self.clock.position())
+    }
+
+    #[dbus_interface(property)]
+    fn can_go_next(&self) -> bool { self.can_go(true) }
+
+    #[dbus_interface(property)]
+    fn can_go_previous(&self) -> bool { self.can_go(false) }
+
+    #[dbus_interface(property)]
+    fn can_play(&self) -> bool { true }
+
+    #[dbus_interface(property)]
+    fn can_pause(&self) -> bool { true }
+
+    #[dbus_interface(property)]
+    fn can_seek(&self) -> bool { self.seekable() }
+
+    #[dbus_interface(property)]
+    fn can_control(&self) -> bool { true }
+}
+
+/// Turns the metadata into the map that mpris uses
+pub fn metadata_map(track_id: OwnedObjectPath, metadata: Option<&Metadata>) -> HashMap<&'static str, Value<'static>> {
+    let mut map = HashMap::new();
+    map.insert("mpris:trackid", track_id.into());
+
+    let Some(metadata) = metadata else { return map };
+    let Metadata {
+        title, album, artist: _, album_artist, artists, track_number, disc_number,
+        genres, path, cover_url, duration, rating, loved: _, station, stream,
+        podcast: _, description,
+    } = metadata;
+
+    if let Some(duration) = duration {
+        map.insert("mpris:length", micros(*duration).into());
+    }
+    if let Some(title) = title {
+        map.insert("xesam:title", title.clone().into());
+    }
+    // stations show up where the album would
+    if let Some(album) = album.as_ref().or(station.as_ref()) {
+        map.insert("xesam:album", album.clone().into());
+    }
+    if !artists.is_empty() {
+        map.insert("xesam:artist", artists.clone().into());
+    }
+    if let Some(album_artist) = album_artist {
+        map.insert("xesam:albumArtist", vec![album_artist.clone()].into());
+    }
+    if let Some(track_number) = track_number.and_then(|n| i32::try_from(n).ok()) {
+        map.insert("xesam:trackNumber", track_number.into());
+    }
+    if let Some(disc_number) = disc_number.and_then(|n| i32::try_from(n).ok()) {
+        map.insert("xesam:discNumber", disc_number.into());
+    }
+    if !genres.is_empty() {
+        map.insert("xesam:genre", genres.clone().into());
+    }
+    if *stream {
+        if let Some(url) = path {
+            map.insert("xesam:url", url.clone().into());
+        }
+    } else if let Some(url) = path.as_ref().and_then(|path| Url::from_file_path(path).ok()) {
+        map.insert("xesam:url", url.to_string().into());
+    }
+    if let Some(cover_url) = cover_url {
+        map.insert("mpris:artUrl", cover_url.clone().into());
+    }
+    if let Some(description) = description {
+        map.insert("xesam:comment", vec![description.clone()].into());
+    }
+    if let Some(rating) = rating {
+        map.insert("xesam:userRating", (f64::from(*rating) / 100.0).into());
+    }
+    map
+}
+
+/// Converts a duration into the microseconds mpris uses
+pub fn micros(duration: Duration) -> i64 {
+    duration.as_micros().try_into().unwrap_or(i64::MAX)
+}
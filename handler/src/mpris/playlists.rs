@@ -0,0 +1,90 @@
+use zbus::{dbus_interface, SignalContext, zvariant::{ObjectPath, OwnedObjectPath}};
+
+use crate::{messages::MessageSender, media::{MediaControlEvent, Playlist}};
+
+use super::{SharedState, stable_hash};
+
+const PLAYLIST_PATH: &str = "/com/github/baanan/musicbee_linux/playlist";
+
+/// How mpris describes a playlist: the id, name and icon
+pub type MprisPlaylist = (OwnedObjectPath, String, String);
+
+/// The `org.mpris.MediaPlayer2.Playlists` interface
+pub struct Playlists {
+    state: SharedState,
+    sender: MessageSender,
+}
+
+impl Playlists {
+    pub const fn new(state: SharedState, sender: MessageSender) -> Self {
+        Self { state, sender }
+    }
+}
+
+/// Creates an id for the playlist that stays the same between runs
+pub fn playlist_id(playlist: &Playlist) -> OwnedObjectPath {
+    OwnedObjectPath::try_from(format!("{PLAYLIST_PATH}/{:016x}", stable_hash(&playlist.url)))
+        .expect("playlist ids are valid object paths")
+}
+
+fn describe(playlist: &Playlist) -> MprisPlaylist {
+    // musicbee doesn't have icons for playlists
+    (playlist_id(playlist), playlist.name.clone(), String::new())
+}
+
+/// The playlists that are still around, but under a different name
+pub fn renamed(old: &[Playlist], new: &[Playlist]) -> Vec<MprisPlaylist> {
+    new.iter()
+        .filter(|playlist| old.iter().any(|old| old.url == playlist.url && old.name != playlist.name))
+        .map(describe)
+        .collect()
+}
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2.Playlists")]
+impl Playlists {
+    fn activate_playlist(&self, id: ObjectPath<'_>) {
+        let playlists = self.state.lock().playlists.clone();
+        let playlist = playlists.iter().find(|playlist| id == *playlist_id(playlist));
+
+        if let Some(playlist) = playlist {
+            self.sender.media_control_event(MediaControlEvent::PlayPlaylist(playlist.url.clone()));
+        }
+    }
+
+    fn get_playlists(&self, index: u32, max_count: u32, order: &str, reverse_order: bool) -> Vec<MprisPlaylist> {
+        let playlists = self.state.lock().playlists.clone();
+        let mut playlists: Vec<_> = playlists.iter().collect();
+
+        // anything else keeps musicbee's order
+        if order == "Alphabetical" {
+            playlists.sort_by_cached_key(|playlist| playlist.name.to_lowercase());
+        }
+        if reverse_order {
+            playlists.reverse();
+        }
+
+        playlists.into_iter()
+            .skip(index as usize)
+            .take(max_count as usize)
+            .map(describe)
+            .collect()
+    }
+
+    #[dbus_interface(signal)]
+    pub async fn playlist_changed(context: &SignalContext<'_>, playlist: MprisPlaylist) -> zbus::Result<()>;
+
+    #[dbus_interface(property)]
+    fn playlist_count(&self) -> u32 {
+        self.state.lock().playlists.len().try_into().unwrap_or(u32::MAX)
+    }
+
+    #[dbus_interface(property)]
+    fn orderings(&self) -> Vec<&str> { vec!["Alphabetical", "UserDefined"] }
+
+    /// The plugin can't tell which playlist is playing
+    #[dbus_interface(property)]
+    fn active_playlist(&self) -> (bool, MprisPlaylist) {
+        let none = OwnedObjectPath::try_from("/").expect("/ is a valid object path");
+        (false, (none, String::new(), String::new()))
+    }
+}
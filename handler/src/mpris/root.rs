@@ -0,0 +1,51 @@
+use zbus::dbus_interface;
+
+use crate::{config::Config, messages::MessageSender, media::MediaControlEvent};
+
+/// The `org.mpris.MediaPlayer2` interface
+pub struct Root {
+    sender: MessageSender,
+    identity: String,
+    can_raise: bool,
+    can_quit: bool,
+}
+
+impl Root {
+    pub fn new(sender: MessageSender, config: &Config) -> Self {
+        Self {
+            sender,
+            identity: config.display_name(),
+            can_raise: config.media_controls.raise.is_some(),
+            can_quit: config.media_controls.quit,
+        }
+    }
+}
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2")]
+impl Root {
+    fn raise(&self) {
+        self.sender.media_control_event(MediaControlEvent::Raise);
+    }
+
+    fn quit(&self) {
+        self.sender.media_control_event(MediaControlEvent::Quit);
+    }
+
+    #[dbus_interface(property)]
+    fn can_raise(&self) -> bool { self.can_raise }
+
+    #[dbus_interface(property)]
+    fn can_quit(&self) -> bool { self.can_quit }
+
+    #[dbus_interface(property)]
+    fn has_track_list(&self) -> bool { true }
+
+    #[dbus_interface(property)]
+    fn identity(&self) -> &str { &self.identity }
+
+    #[dbus_interface(property)]
+    fn supported_uri_schemes(&self) -> Vec<&str> { vec!["file"] }
+
+    #[dbus_interface(property)]
+    fn supported_mime_types(&self) -> Vec<&str> { Vec::new() }
+}
@@ -0,0 +1,322 @@
+//! Serves the player on a private bus from `dbus-daemon`, and talks to it like a media widget would
+//!
+//! These need `dbus-daemon` installed, so they only run with `cargo test -- --ignored`
+
+use std::{collections::HashMap, io::{BufRead, BufReader}, process::{Child, Command as Process, Stdio}, sync::Arc, time::Duration};
+
+use futures::StreamExt;
+use tokio::{sync::broadcast::Receiver, time};
+use zbus::{Connection, ConnectionBuilder, Proxy, ProxyBuilder, CacheProperties, zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Structure, StructureBuilder, Value}};
+
+use crate::{config::Config, messages::{Messages, Command}, media::{Metadata, MediaControlEvent, MediaPlayback, MediaPosition, Queue, QueueTrack, Playlist, SeekDirection}};
+
+use super::{Server, SharedState, State, PATH};
+
+const BUS_NAME: &str = "test";
+
+const PLAYER: &str = "org.mpris.MediaPlayer2.Player";
+const TRACK_LIST: &str = "org.mpris.MediaPlayer2.TrackList";
+const PLAYLISTS: &str = "org.mpris.MediaPlayer2.Playlists";
+
+/// A `dbus-daemon` that's killed once the test is over
+struct Bus(Child);
+
+impl Bus {
+    /// Starts a new session bus, returning it with its address
+    fn start() -> (Self, String) {
+        let mut daemon = Process::new("dbus-daemon")
+            .args(["--session", "--print-address", "--nofork"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("dbus-daemon is installed");
+
+        let mut address = String::new();
+        let stdout = daemon.stdout.take().expect("the output is piped");
+        BufReader::new(stdout).read_line(&mut address).expect("dbus-daemon prints its address");
+        (Self(daemon), address.trim().to_owned())
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// A player being served on its own bus, with a client connected to it
+struct Fixture {
+    _bus: Bus,
+    server: Server,
+    // kept around so that sending commands doesn't fail
+    _messages: Messages,
+    state: SharedState,
+    commands: Receiver<Command>,
+    client: Connection,
+}
+
+impl Fixture {
+    /// Serves the player in the middle of [`playing`]
+    async fn start() -> Self {
+        let (bus, address) = Bus::start();
+        let state = SharedState::default();
+        *state.lock() = playing();
+
+        let config = Config::default();
+        let messages = Messages::new(Arc::new(config.clone()));
+        let sender = messages.sender();
+        let commands = sender.subscribe();
+
+        let builder = ConnectionBuilder::address(address.as_str()).expect("the address is valid");
+        let server = Server::start(builder, BUS_NAME, state.clone(), sender, &config).await
+            .expect("the server starts");
+        let client = ConnectionBuilder::address(address.as_str()).expect("the address is valid")
+            .build().await
+            .expect("the client connects");
+
+        Self { _bus: bus, server, _messages: messages, state, commands, client }
+    }
+
+    /// Puts the state back to [`playing`], then changes it for the next case
+    fn reset(&self, change: fn(&mut State)) {
+        let mut state = self.state.lock();
+        *state = playing();
+        change(&mut state);
+    }
+
+    async fn proxy(&self, interface: &'static str) -> Proxy<'static> {
+        ProxyBuilder::new_bare(&self.client)
+            .destination(format!("org.mpris.MediaPlayer2.{BUS_NAME}")).expect("the name is valid")
+            .path(PATH).expect("the path is valid")
+            .interface(interface).expect("the interface is valid")
+            // the state is changed under the proxy without any signals
+            .cache_properties(CacheProperties::No)
+            .build().await
+            .expect("the proxy is created")
+    }
+
+    /// The next media control event that the interfaces sent
+    async fn event(&mut self) -> Option<MediaControlEvent> {
+        let commands = &mut self.commands;
+        time::timeout(Duration::from_secs(1), async {
+            loop {
+                if let Command::MediaControlEvent(event) = commands.recv().await.ok()? {
+                    return Some((*event).clone());
+                }
+            }
+        }).await.ok().flatten()
+    }
+}
+
+fn track(index: usize, title: &str) -> QueueTrack {
+    QueueTrack {
+        index,
+        metadata: Metadata {
+            title: Some(title.to_owned()),
+            path: Some(format!("/music/{title}.flac")),
+            ..Metadata::default()
+        },
+    }
+}
+
+/// The second of three tracks, ten seconds in
+fn playing() -> State {
+    State {
+        metadata: Some(Arc::new(Metadata {
+            duration: Some(Duration::from_secs(180)),
+            ..track(1, "second").metadata
+        })),
+        playback: Arc::new(MediaPlayback::Playing { progress: Some(MediaPosition(Duration::from_secs(10))) }),
+        volume: 0.5,
+        queue: Some(Arc::new(Queue {
+            current: Some(1),
+            tracks: vec![track(0, "first"), track(1, "second"), track(2, "third")],
+        })),
+        playlists: Arc::new(vec![
+            Playlist { url: "Playlists/Zebra.mbp".to_owned(), name: "Zebra".to_owned() },
+            Playlist { url: "Playlists/Aardvark.mbp".to_owned(), name: "Aardvark".to_owned() },
+        ]),
+        ..State::default()
+    }
+}
+
+fn unchanged(_: &mut State) {}
+
+fn end_of_queue(state: &mut State) {
+    state.queue = Some(Arc::new(Queue { current: Some(1), tracks: vec![track(0, "first"), track(1, "second")] }));
+}
+
+fn stream(state: &mut State) {
+    state.metadata = Some(Arc::new(Metadata { stream: true, ..Metadata::default() }));
+}
+
+/// The first track is queued up again after the playing one
+fn repeated_track(state: &mut State) {
+    state.queue = Some(Arc::new(repeated_queue()));
+}
+
+fn repeated_queue() -> Queue {
+    Queue { current: Some(1), tracks: vec![track(0, "first"), track(1, "second"), track(2, "first")] }
+}
+
+fn track_id(title: &str) -> OwnedObjectPath {
+    super::player::track_id(Some(&track(0, title).metadata))
+}
+
+fn body(fields: Vec<Value<'static>>) -> Structure<'static> {
+    fields.into_iter().fold(StructureBuilder::new(), StructureBuilder::append_field).build()
+}
+
+/// A property that should have a value after the state is changed
+struct PropertyCase {
+    case: &'static str,
+    change: fn(&mut State),
+    interface: &'static str,
+    property: &'static str,
+    expected: Value<'static>,
+}
+
+/// A method call that should send an event after the state is changed, or nothing
+struct MethodCase {
+    case: &'static str,
+    change: fn(&mut State),
+    interface: &'static str,
+    method: &'static str,
+    body: Structure<'static>,
+    expected: Option<MediaControlEvent>,
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "needs dbus-daemon"]
+async fn properties() {
+    let fixture = Fixture::start().await;
+
+    let cases = [
+        PropertyCase { case: "playing", change: unchanged, interface: PLAYER, property: "PlaybackStatus", expected: Value::from("Playing") },
+        PropertyCase { case: "volume", change: unchanged, interface: PLAYER, property: "Volume", expected: Value::from(0.5) },
+        PropertyCase { case: "seekable", change: unchanged, interface: PLAYER, property: "CanSeek", expected: Value::from(true) },
+        PropertyCase { case: "streams can't be seeked", change: stream, interface: PLAYER, property: "CanSeek", expected: Value::from(false) },
+        PropertyCase { case: "next in the queue", change: unchanged, interface: PLAYER, property: "CanGoNext", expected: Value::from(true) },
+        PropertyCase { case: "end of the queue", change: end_of_queue, interface: PLAYER, property: "CanGoNext", expected: Value::from(false) },
+        PropertyCase { case: "previous in the queue", change: unchanged, interface: PLAYER, property: "CanGoPrevious", expected: Value::from(true) },
+        PropertyCase { case: "previous at the end of the queue", change: end_of_queue, interface: PLAYER, property: "CanGoPrevious", expected: Value::from(true) },
+        PropertyCase { case: "playlists", change: unchanged, interface: PLAYLISTS, property: "PlaylistCount", expected: Value::from(2_u32) },
+    ];
+
+    for PropertyCase { case, change, interface, property, expected } in cases {
+        fixture.reset(change);
+        let value: OwnedValue = fixture.proxy(interface).await.get_property(property).await.unwrap();
+        assert_eq!(value, expected.into(), "{case}");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "needs dbus-daemon"]
+async fn methods() {
+    let mut fixture = Fixture::start().await;
+
+    let repeated_id = super::track_list::queue_ids(&repeated_queue())[2].0.clone();
+    let zebra = super::playlists::playlist_id(&playing().playlists[0]);
+    let other = ObjectPath::try_from("/com/github/baanan/musicbee_linux/track/other").unwrap();
+
+    let cases = [
+        MethodCase {
+            case: "set position",
+            change: unchanged, interface: PLAYER, method: "SetPosition",
+            body: body(vec![track_id("second").into(), 30_000_000_i64.into()]),
+            expected: Some(MediaControlEvent::SetPosition(MediaPosition(Duration::from_secs(30)))),
+        },
+        MethodCase {
+            case: "positions for other tracks are ignored",
+            change: unchanged, interface: PLAYER, method: "SetPosition",
+            body: body(vec![other.into(), 30_000_000_i64.into()]),
+            expected: None,
+        },
+        MethodCase {
+            case: "seek",
+            change: unchanged, interface: PLAYER, method: "Seek",
+            body: body(vec![(-5_000_000_i64).into()]),
+            expected: Some(MediaControlEvent::SeekBy(SeekDirection::Backward, Duration::from_secs(5))),
+        },
+        MethodCase {
+            case: "streams can't be seeked",
+            change: stream, interface: PLAYER, method: "Seek",
+            body: body(vec![5_000_000_i64.into()]),
+            expected: None,
+        },
+        MethodCase {
+            case: "go to",
+            change: unchanged, interface: TRACK_LIST, method: "GoTo",
+            body: body(vec![track_id("third").into()]),
+            expected: Some(MediaControlEvent::GoTo(2)),
+        },
+        MethodCase {
+            case: "later copies of a track can't be gone to",
+            change: repeated_track, interface: TRACK_LIST, method: "GoTo",
+            body: body(vec![repeated_id.into()]),
+            expected: None,
+        },
+        MethodCase {
+            case: "activate playlist",
+            change: unchanged, interface: PLAYLISTS, method: "ActivatePlaylist",
+            body: body(vec![zebra.into()]),
+            expected: Some(MediaControlEvent::PlayPlaylist("Playlists/Zebra.mbp".to_owned())),
+        },
+    ];
+
+    for MethodCase { case, change, interface, method, body, expected } in cases {
+        fixture.reset(change);
+        fixture.proxy(interface).await.call_method(method, &body).await.unwrap();
+        assert_eq!(fixture.event().await, expected, "{case}");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "needs dbus-daemon"]
+async fn queries() {
+    let fixture = Fixture::start().await;
+    let player = fixture.proxy(PLAYER).await;
+    let track_list = fixture.proxy(TRACK_LIST).await;
+    let playlists = fixture.proxy(PLAYLISTS).await;
+
+    let metadata: HashMap<String, OwnedValue> = player.get_property("Metadata").await.unwrap();
+    assert_eq!(metadata["xesam:title"], Value::from("second").into());
+    assert_eq!(metadata["mpris:length"], Value::from(180_000_000_i64).into());
+    assert_eq!(metadata["mpris:trackid"], Value::from(track_id("second")).into());
+
+    let tracks: Vec<OwnedObjectPath> = track_list.get_property("Tracks").await.unwrap();
+    assert_eq!(tracks, [track_id("first"), track_id("second"), track_id("third")]);
+
+    let metadata: Vec<HashMap<String, OwnedValue>> = track_list.call("GetTracksMetadata", &(vec![track_id("third")],)).await.unwrap();
+    assert_eq!(metadata.len(), 1);
+    assert_eq!(metadata[0]["xesam:title"], Value::from("third").into());
+
+    let listed: Vec<(OwnedObjectPath, String, String)> = playlists
+        .call("GetPlaylists", &(0_u32, 10_u32, "Alphabetical", false))
+        .await.unwrap();
+    let names: Vec<_> = listed.iter().map(|(_, name, _)| name.as_str()).collect();
+    assert_eq!(names, ["Aardvark", "Zebra"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "needs dbus-daemon"]
+async fn renamed_playlists() {
+    let fixture = Fixture::start().await;
+    let playlists = fixture.proxy(PLAYLISTS).await;
+    let mut changes = playlists.receive_signal("PlaylistChanged").await.unwrap();
+
+    let old = fixture.state.lock().playlists.clone();
+    let new = vec![
+        Playlist { url: "Playlists/Zebra.mbp".to_owned(), name: "Zebras".to_owned() },
+        Playlist { url: "Playlists/Aardvark.mbp".to_owned(), name: "Aardvark".to_owned() },
+    ];
+    fixture.state.lock().playlists = Arc::new(new.clone());
+    fixture.server.playlists_changed(&old, &new).await.unwrap();
+
+    let change = time::timeout(Duration::from_secs(1), changes.next()).await
+        .expect("the playlist change is sent")
+        .unwrap();
+    let (_, name, _): (OwnedObjectPath, String, String) = change.body().unwrap();
+    assert_eq!(name, "Zebras");
+}
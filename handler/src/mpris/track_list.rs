@@ -0,0 +1,92 @@
+use std::collections::{HashMap, HashSet};
+
+use zbus::{dbus_interface, SignalContext, zvariant::{ObjectPath, OwnedObjectPath, Value}};
+
+use crate::{messages::MessageSender, media::{MediaControlEvent, Queue, QueueTrack}};
+
+use super::{SharedState, player::{track_id, metadata_map}};
+
+/// The `org.mpris.MediaPlayer2.TrackList` interface
+///
+/// Only the window of the now playing list that the plugin sends is published
+pub struct TrackList {
+    state: SharedState,
+    sender: MessageSender,
+}
+
+impl TrackList {
+    pub const fn new(state: SharedState, sender: MessageSender) -> Self {
+        Self { state, sender }
+    }
+}
+
+/// Gives every track in the queue an id
+///
+/// Tracks get the same id as they would in the player's metadata, but when a file is in the queue
+/// more than once, the copies that aren't playing get their index added on to keep them unique
+pub fn queue_ids(queue: &Queue) -> Vec<(OwnedObjectPath, &QueueTrack)> {
+    let mut taken = HashSet::new();
+    if let Some(current) = queue.current_track() {
+        taken.insert(track_id(Some(&current.metadata)));
+    }
+
+    queue.tracks.iter()
+        .map(|track| {
+            let id = track_id(Some(&track.metadata));
+            if Some(track.index) == queue.current || taken.insert(id.clone()) {
+                return (id, track);
+            }
+            let id = OwnedObjectPath::try_from(format!("{}/{}", id.as_str(), track.index))
+                .expect("track ids are valid object paths");
+            (id, track)
+        })
+        .collect()
+}
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2.TrackList")]
+impl TrackList {
+    fn get_tracks_metadata(&self, track_ids: Vec<ObjectPath<'_>>) -> Vec<HashMap<&'static str, Value<'static>>> {
+        let Some(queue) = self.state.lock().queue.clone() else { return Vec::new() };
+        let ids = queue_ids(&queue);
+
+        track_ids.iter()
+            .filter_map(|requested| ids.iter().find(|(id, _)| *requested == **id))
+            .map(|(id, track)| metadata_map(id.clone(), Some(&track.metadata)))
+            .collect()
+    }
+
+    /// The tracks can't be edited, so this does nothing
+    fn add_track(&self, _uri: String, _after_track: ObjectPath<'_>, _set_as_current: bool) {}
+
+    /// The tracks can't be edited, so this does nothing
+    fn remove_track(&self, _track_id: ObjectPath<'_>) {}
+
+    fn go_to(&self, track_id: ObjectPath<'_>) {
+        let Some(queue) = self.state.lock().queue.clone() else { return };
+        let Some((_, track)) = queue_ids(&queue).into_iter().find(|(id, _)| track_id == **id) else { return };
+
+        // musicbee can only play a file, which starts its first copy in the list
+        let later_copy = queue.tracks.iter()
+            .any(|other| other.index < track.index && other.metadata.path == track.metadata.path);
+        if later_copy { return; }
+
+        self.sender.media_control_event(MediaControlEvent::GoTo(track.index));
+    }
+
+    #[dbus_interface(signal)]
+    pub async fn track_list_replaced(
+        context: &SignalContext<'_>,
+        tracks: Vec<OwnedObjectPath>,
+        current_track: OwnedObjectPath,
+    ) -> zbus::Result<()>;
+
+    #[dbus_interface(property)]
+    fn tracks(&self) -> Vec<OwnedObjectPath> {
+        let queue = self.state.lock().queue.clone();
+        queue.map(|queue| queue_ids(&queue).into_iter().map(|(id, _)| id).collect())
+            .unwrap_or_default()
+    }
+
+    #[dbus_interface(property)]
+    fn can_edit_tracks(&self) -> bool { false }
+}
@@ -0,0 +1,36 @@
+use anyhow::{Result, Context, bail};
+
+use crate::{cli::PlaylistsCommand, config::Config, communication::Action, ctl, filesystem::{self, PLAYLISTS_FILE}, media::Playlist};
+
+pub fn run(config: &Config, command: &PlaylistsCommand) -> Result<()> {
+    let playlists = read(config)?;
+
+    match command {
+        PlaylistsCommand::List => {
+            for playlist in &playlists {
+                println!("{}", playlist.name);
+            }
+            Ok(())
+        },
+        PlaylistsCommand::Play { name } => {
+            let Some(playlist) = find(&playlists, name) else {
+                bail!("there isn't a playlist called {name}, see `playlists list`");
+            };
+            ctl::send(config, &Action::PlayPlaylist(playlist.url.clone()))
+        },
+    }
+}
+
+/// Reads the playlists that the plugin last published
+fn read(config: &Config) -> Result<Vec<Playlist>> {
+    let contents = std::fs::read_to_string(config.get_comm_path(PLAYLISTS_FILE))
+        .context("failed to read the playlists")?;
+    filesystem::parse_playlists(&contents)
+        .context("failed to parse the playlists")
+}
+
+/// Finds the playlist with the name, preferring ones with the same capitalization
+fn find<'a>(playlists: &'a [Playlist], name: &str) -> Option<&'a Playlist> {
+    playlists.iter().find(|playlist| playlist.name == name)
+        .or_else(|| playlists.iter().find(|playlist| playlist.name.eq_ignore_ascii_case(name)))
+}
@@ -0,0 +1,57 @@
+use anyhow::{Result, Context, bail};
+
+use crate::{cli::PodcastsCommand, config::Config, communication::Action, ctl, filesystem::{self, PODCASTS_FILE}, media::Subscription};
+
+pub fn run(config: &Config, command: &PodcastsCommand) -> Result<()> {
+    let subscriptions = crate::run_async(list(config))?;
+
+    match command {
+        PodcastsCommand::List { json: true } => {
+            println!("{}", serde_json::to_string(&subscriptions).context("failed to serialize the podcasts")?);
+            Ok(())
+        },
+        PodcastsCommand::List { json: false } => {
+            for subscription in &subscriptions {
+                println!("{}", subscription.title);
+                for (number, episode) in subscription.episodes.iter().enumerate() {
+                    let details: Vec<_> = [&episode.date, &episode.duration].into_iter().flatten().map(String::as_str).collect();
+                    println!(
+                        "  {:>4}  {}{}{}",
+                        number + 1,
+                        episode.title.as_deref().unwrap_or("Unknown Episode"),
+                        if details.is_empty() { String::new() } else { format!(" ({})", details.join(", ")) },
+                        if episode.downloaded { "" } else { " [not downloaded]" },
+                    );
+                }
+            }
+            Ok(())
+        },
+        PodcastsCommand::Play { podcast, episode } => {
+            let Some(subscription) = find(&subscriptions, podcast) else {
+                bail!("there isn't a podcast called {podcast}, see `podcasts list`");
+            };
+            let episode = usize::try_from(*episode - 1).ok()
+                .and_then(|index| subscription.episodes.get(index))
+                .with_context(|| format!("{} doesn't have that many unplayed episodes", subscription.title))?;
+
+            // musicbee gave the url, so it doesn't need to be mapped
+            ctl::send(config, &Action::PlayNow(vec![episode.url.clone()]))
+        },
+    }
+}
+
+/// Asks the plugin for the subscriptions, waiting for it to write them
+async fn list(config: &Config) -> Result<Vec<Subscription>> {
+    ctl::request(
+        config,
+        |id| Action::Podcasts { id },
+        PODCASTS_FILE,
+        filesystem::parse_podcasts,
+    ).await.context("failed to list the podcasts")
+}
+
+/// Finds the podcast with the title, preferring ones with the same capitalization
+fn find<'a>(subscriptions: &'a [Subscription], title: &str) -> Option<&'a Subscription> {
+    subscriptions.iter().find(|subscription| subscription.title == title)
+        .or_else(|| subscriptions.iter().find(|subscription| subscription.title.eq_ignore_ascii_case(title)))
+}
@@ -0,0 +1,66 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, Context};
+
+use crate::{cli::QueueCommand, config::Config, communication::Action, ctl, filesystem::{self, QUEUE_FILE}};
+
+/// Prints the part of the now playing list that the plugin last published
+///
+/// Tracks are numbered from 1, with the playing track marked by a `>`
+pub fn print(config: &Config) -> Result<()> {
+    let contents = std::fs::read_to_string(config.get_comm_path(QUEUE_FILE))
+        .context("failed to read the queue")?;
+    let queue = filesystem::parse_queue(&contents, config)
+        .context("failed to parse the queue")?;
+
+    for track in &queue.tracks {
+        let marker = if Some(track.index) == queue.current { '>' } else { ' ' };
+        let metadata = &track.metadata;
+        println!(
+            "{marker} {:>4}  {} - {}",
+            track.index + 1,
+            metadata.artist.as_deref().unwrap_or("Unknown Artist"),
+            metadata.title.as_deref().unwrap_or("Unknown Title"),
+        );
+    }
+    Ok(())
+}
+
+/// Changes the now playing list through the plugin
+pub fn run(config: &Config, command: &QueueCommand) -> Result<()> {
+    let action = match command {
+        QueueCommand::PlayNow { files } => Action::PlayNow(unmap_files(files, config)?),
+        QueueCommand::Next { files } => Action::QueueNext(unmap_files(files, config)?),
+        QueueCommand::Last { files } => Action::QueueLast(unmap_files(files, config)?),
+        QueueCommand::Remove { position } => Action::RemoveFromQueue(index(*position)?),
+        QueueCommand::Move { positions, to } => Action::MoveInQueue {
+            from: positions.iter().map(|position| index(*position)).collect::<Result<_>>()?,
+            to: index(*to)?,
+        },
+        QueueCommand::Clear => Action::ClearQueue,
+    };
+
+    ctl::send(config, &action)
+}
+
+/// Turns the files into paths that MusicBee can open
+fn unmap_files(files: &[PathBuf], config: &Config) -> Result<Vec<String>> {
+    files.iter()
+        .map(|file| unmap_file(file, config))
+        .collect()
+}
+
+fn unmap_file(file: &Path, config: &Config) -> Result<String> {
+    let path = file.canonicalize()
+        .with_context(|| format!("failed to find {}", file.display()))?;
+    let path = path.to_str()
+        .with_context(|| format!("{} isn't valid unicode", path.display()))?;
+    Ok(config.unmap_filename(path))
+}
+
+/// Turns a position from the command line into an index of the now playing list
+fn index(position: u64) -> Result<usize> {
+    let position = usize::try_from(position).context("the position is too large")?;
+    // the positions start at 1
+    Ok(position - 1)
+}
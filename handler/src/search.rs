@@ -0,0 +1,48 @@
+use anyhow::{Result, Context, bail};
+
+use crate::{cli::SearchArgs, config::Config, communication::{Action, SearchQuery}, ctl, filesystem::{self, SEARCH_RESULTS_FILE}, media::SearchResult};
+
+pub fn run(config: &Config, args: &SearchArgs) -> Result<()> {
+    let query = SearchQuery {
+        artist: args.artist.clone(),
+        album: args.album.clone(),
+        title: args.title.clone(),
+        text: (!args.text.is_empty()).then(|| args.text.join(" ")),
+        limit: args.limit,
+    };
+
+    let results = crate::run_async(search(config, query))?;
+
+    if args.json {
+        println!("{}", serde_json::to_string(&results).context("failed to serialize the results")?);
+    } else {
+        for result in &results {
+            println!(
+                "{} - {} ({})",
+                result.artist.as_deref().unwrap_or("Unknown Artist"),
+                result.title.as_deref().unwrap_or("Unknown Title"),
+                result.album.as_deref().unwrap_or("Unknown Album"),
+            );
+        }
+    }
+
+    if !(args.play || args.queue) { return Ok(()); }
+    if results.is_empty() {
+        bail!("nothing matched the search");
+    }
+
+    // musicbee gave the files, so they don't need to be mapped
+    let files = results.into_iter().map(|result| result.file).collect();
+    let action = if args.play { Action::PlayNow(files) } else { Action::QueueLast(files) };
+    ctl::send(config, &action)
+}
+
+/// Asks the plugin to search the library, waiting for it to write the results
+pub async fn search(config: &Config, query: SearchQuery) -> Result<Vec<SearchResult>> {
+    ctl::request(
+        config,
+        |id| Action::Search { id, query },
+        SEARCH_RESULTS_FILE,
+        |contents| filesystem::parse_search_results(contents, config),
+    ).await.context("failed to search")
+}
@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use anyhow::{Result, Context};
+use async_trait::async_trait;
+
+use crate::{config::{Config, self}, messages::{Command, MessageSender}, communication::Action, listener::Listener, filesystem::Filesystem};
+
+pub mod socket;
+
+use socket::Socket;
+
+/// A way for the handler to talk to the plugin
+///
+/// Every transport sends the state it recieves from the plugin through a [`MessageSender`]
+#[async_trait]
+pub trait Transport {
+    /// Starts recieving state from the plugin
+    async fn start(&mut self, config: &Config) -> Result<()>;
+
+    /// Asks for every piece of state from the plugin
+    async fn update(&mut self, config: &Config) -> Result<()>;
+
+    /// Sends an action over to the plugin
+    async fn send(&mut self, action: &Action, config: &Config) -> Result<()>;
+
+    /// Handles any other command, in case the transport needs it
+    async fn handle(&mut self, _command: Command, _config: &Config) -> Result<()> { Ok(()) }
+
+    fn name(&self) -> &'static str;
+}
+
+/// Creates the transport chosen in the config
+pub fn create(sender: MessageSender, config: &Arc<Config>) -> Connection {
+    let transport: Box<dyn Transport + Send> = match config.communication.transport {
+        config::Transport::Directory => Box::new(Filesystem::new(sender, config.clone())),
+        config::Transport::Socket { port } => Box::new(Socket::new(sender, config.clone(), port)),
+    };
+    Connection { transport }
+}
+
+/// Listens to commands on behalf of a [`Transport`]
+pub struct Connection {
+    transport: Box<dyn Transport + Send>,
+}
+
+#[async_trait]
+impl Listener for Connection {
+    async fn handle(&mut self, command: Command, config: &Config) -> Result<()> {
+        let Self { transport } = self;
+        match command {
+            Command::Update =>
+                transport.update(config).await.context("failed to update handlers")?,
+            Command::Action(action) =>
+                transport.send(&action, config).await.context("failed to send action")?,
+            command => transport.handle(command, config).await?,
+        }
+        Ok(())
+    }
+
+    async fn start(&mut self, config: &Config) -> Result<()> {
+        self.transport.start(config).await
+    }
+
+    fn name(&self) -> &'static str { self.transport.name() }
+}
@@ -0,0 +1,265 @@
+use std::{sync::Arc, net::{Ipv4Addr, SocketAddr}, fs::OpenOptions, io::{self, Read, Write}, os::unix::fs::OpenOptionsExt, time::Duration, fmt::Write as _};
+
+use anyhow::{Result, Context, bail};
+use async_trait::async_trait;
+use log::*;
+use serde::{Serialize, Deserialize};
+use tokio::{net::{TcpListener, TcpStream, tcp::{OwnedWriteHalf, OwnedReadHalf}}, sync::Mutex, task::JoinHandle, io::{AsyncBufReadExt, BufReader, AsyncWriteExt, Lines}, time};
+
+use crate::{config::Config, messages::{MessageSender, AttachReason}, communication::Action, filesystem};
+
+use super::Transport;
+
+/// The file in the communication directory with the token that the plugin has to send first
+///
+/// Any local user can connect to the socket, but only the current user can read the directory
+pub const TOKEN_FILE: &str = "socket-token";
+
+/// How long a connection has to send the token before it's dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Frames sent from the plugin, or from the command line
+///
+/// The contents of a state frame are the same as what would be written to the comm file of the
+/// same name, so they're parsed the same way
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum Incoming {
+    /// The first frame of every connection from the plugin
+    Hello { token: String },
+    State { name: String, contents: String },
+    /// The only frame of a connection from the command line, with an action to pass on to the plugin
+    Forward { token: String, action: String },
+}
+
+/// Frames sent to the plugin, or back to the command line
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum Outgoing {
+    Action { action: String },
+    Update,
+    /// Whether a forwarded action made it to the plugin
+    Forwarded { sent: bool },
+}
+
+/// The connection that the plugin is using, which is the newest one that authenticated
+struct Plugin {
+    id: u64,
+    writer: OwnedWriteHalf,
+}
+
+type Writer = Arc<Mutex<Option<Plugin>>>;
+
+/// Talks to the plugin over a localhost tcp socket
+///
+/// Each frame is a single line of json
+pub struct Socket {
+    sender: MessageSender,
+    config: Arc<Config>,
+    port: u16,
+    writer: Writer,
+    server: Option<JoinHandle<()>>,
+}
+
+impl Socket {
+    pub fn new(sender: MessageSender, config: Arc<Config>, port: u16) -> Self {
+        Self { sender, config, port, writer: Arc::default(), server: None }
+    }
+
+    async fn write(&self, frame: &Outgoing) -> Result<()> {
+        write_to_plugin(&self.writer, frame).await
+    }
+}
+
+#[async_trait]
+impl Transport for Socket {
+    async fn start(&mut self, config: &Config) -> Result<()> {
+        let token: Arc<str> = create_token(config)?.into();
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, self.port)).await
+            .with_context(|| format!("failed to listen on port {}, is another instance using it?", self.port))?;
+        debug!("listening for the plugin on port {}", self.port);
+
+        self.server = Some(tokio::spawn(serve(
+            listener,
+            self.writer.clone(),
+            self.sender.clone(),
+            self.config.clone(),
+            token,
+        )));
+        Ok(())
+    }
+
+    async fn update(&mut self, _: &Config) -> Result<()> {
+        self.write(&Outgoing::Update).await
+    }
+
+    async fn send(&mut self, action: &Action, _: &Config) -> Result<()> {
+        debug!("sending action: {action}");
+        self.write(&Outgoing::Action { action: action.to_string() }).await
+    }
+
+    fn name(&self) -> &'static str { "socket" }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        if let Some(server) = self.server.take() {
+            server.abort();
+        }
+    }
+}
+
+/// Writes a new random token that only the current user can read
+fn create_token(config: &Config) -> Result<String> {
+    let mut bytes = [0; 16];
+    std::fs::File::open("/dev/urandom")
+        .and_then(|mut random| random.read_exact(&mut bytes))
+        .context("failed to generate the socket token")?;
+    let token = bytes.iter().fold(String::new(), |mut token, byte| {
+        let _ = write!(token, "{byte:02x}");
+        token
+    });
+
+    OpenOptions::new()
+        .write(true).create(true).truncate(true)
+        .mode(0o600)
+        .open(config.get_comm_path(TOKEN_FILE))
+        .and_then(|mut file| file.write_all(token.as_bytes()))
+        .context("failed to write the socket token")?;
+    Ok(token)
+}
+
+/// Passes an action on to the plugin through the daemon's socket
+///
+/// Returns false if the daemon isn't running, so the action can go through the directory instead
+pub async fn forward(action: &Action, port: u16, config: &Config) -> Result<bool> {
+    let stream = match TcpStream::connect((Ipv4Addr::LOCALHOST, port)).await {
+        Ok(stream) => stream,
+        Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => return Ok(false),
+        Err(err) => return Err(err).with_context(|| format!("failed to connect to the daemon on port {port}")),
+    };
+    let token = config.read_comm_file(TOKEN_FILE).await
+        .context("failed to read the socket token")?;
+
+    let (read, mut write) = stream.into_split();
+    let frame = Incoming::Forward { token: token.trim().to_owned(), action: action.to_string() };
+    write_frame(&mut write, &frame).await?;
+
+    let answer = time::timeout(HANDSHAKE_TIMEOUT, BufReader::new(read).lines().next_line()).await
+        .context("the daemon didn't answer")?
+        .context("failed to read the daemon's answer")?
+        .context("the daemon rejected the socket token")?;
+    match serde_json::from_str(&answer) {
+        Ok(Outgoing::Forwarded { sent: true }) => Ok(true),
+        Ok(Outgoing::Forwarded { sent: false }) => bail!("the plugin isn't connected to the daemon, is MusicBee running?"),
+        _ => bail!("got a malformed answer from the daemon: '{answer}'"),
+    }
+}
+
+async fn write_frame(writer: &mut OwnedWriteHalf, frame: &impl Serialize) -> Result<()> {
+    let mut line = serde_json::to_string(frame)
+        .context("failed to serialize frame")?;
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await
+        .context("failed to write to the socket")
+}
+
+async fn write_to_plugin(writer: &Writer, frame: &Outgoing) -> Result<()> {
+    let mut plugin = writer.lock().await;
+    let Some(Plugin { writer, .. }) = plugin.as_mut() else {
+        bail!("the plugin isn't connected");
+    };
+    write_frame(writer, frame).await
+        .context("failed to write to the plugin")
+}
+
+/// Accepts connections, each of which has to authenticate before it's treated as the plugin
+async fn serve(listener: TcpListener, writer: Writer, sender: MessageSender, config: Arc<Config>, token: Arc<str>) {
+    for id in 0.. {
+        let (stream, address) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(err) => {
+                error!("failed to accept a connection from the plugin: {err}");
+                continue;
+            }
+        };
+
+        tokio::spawn(connection(
+            id, stream, address,
+            writer.clone(), sender.clone(), config.clone(), token.clone(),
+        ));
+    }
+}
+
+async fn connection(
+    id: u64,
+    stream: TcpStream,
+    address: SocketAddr,
+    writer: Writer,
+    sender: MessageSender,
+    config: Arc<Config>,
+    token: Arc<str>,
+) {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+
+    // anything that can't prove it can read the directory isn't the plugin or the command line
+    let first = match time::timeout(HANDSHAKE_TIMEOUT, lines.next_line()).await {
+        Ok(Ok(Some(line))) => serde_json::from_str(&line).ok(),
+        _ => None,
+    };
+    match first {
+        Some(Incoming::Hello { token: sent }) if *sent == *token => (),
+        Some(Incoming::Forward { token: sent, action }) if *sent == *token => {
+            debug!("forwarding action from the command line: {action}");
+            let sent = write_to_plugin(&writer, &Outgoing::Action { action }).await
+                .map_err(|err| warn!("failed to forward an action: {err:?}"))
+                .is_ok();
+            write_frame(&mut write, &Outgoing::Forwarded { sent }).await
+                .unwrap_or_else(|err| warn!("failed to answer the command line: {err:?}"));
+            return;
+        },
+        _ => {
+            warn!("rejected a connection from {address} that didn't send the token");
+            return;
+        },
+    }
+
+    debug!("plugin connected from {address}");
+    *writer.lock().await = Some(Plugin { id, writer: write });
+
+    receive(lines, &sender, &config).await
+        .unwrap_or_else(|err| error!("lost connection to the plugin: {err:?}"));
+
+    // a newer connection already took over from this one
+    let mut plugin = writer.lock().await;
+    if plugin.as_ref().is_some_and(|plugin| plugin.id == id) {
+        debug!("plugin disconnected");
+        plugin.take();
+        drop(plugin);
+
+        // the plugin says when it closes, so this is a reconnect or a crash,
+        // neither of which should exit with `exit_with_plugin`
+        sender.attach_as(false, AttachReason::Plugin);
+    }
+}
+
+async fn receive(mut lines: Lines<BufReader<OwnedReadHalf>>, sender: &MessageSender, config: &Config) -> Result<()> {
+    while let Some(line) = lines.next_line().await? {
+        let frame = match serde_json::from_str(&line) {
+            Ok(frame) => frame,
+            Err(err) => {
+                error!("got malformed frame '{line}': {err}");
+                continue;
+            }
+        };
+
+        match frame {
+            Incoming::Hello { .. } | Incoming::Forward { .. } => (),
+            Incoming::State { name, contents } => filesystem::parse(&name, &contents, sender, config)
+                .unwrap_or_else(|err| error!("failed to handle {name} from the plugin: {err:?}")),
+        }
+    }
+
+    Ok(())
+}
@@ -1,19 +1,56 @@
-use std::sync::Arc;
-
-use tray_item::{TrayItem, IconSource};
-
-use crate::{config::Config, messages::MessageSender, logger};
+use std::{sync::Arc, rc::Rc, cell::Cell};
 
 use anyhow::{Result, Context};
+use async_trait::async_trait;
+use gtk::prelude::*;
+use libappindicator::{AppIndicator, AppIndicatorStatus};
+
+use crate::{config::Config, messages::{MessageSender, Command, Settings, OutputDevices}, logger, communication::{Action, Switch, ReplayGainMode}, listener::Listener};
 
 // TODO: fancier tray (attach toggle, metadata)
 
-pub fn start(message_sender: MessageSender, config: Arc<Config>) -> Result<()> {
+/// Changes to the player that the tray shows
+#[derive(Debug)]
+pub enum Update {
+    Settings(Arc<Settings>),
+    OutputDevices(Arc<OutputDevices>),
+}
+
+/// Passes the updates that the tray needs over to the gtk thread
+pub struct Forwarder {
+    updates: glib::Sender<Update>,
+}
+
+impl Forwarder {
+    pub const fn new(updates: glib::Sender<Update>) -> Self { Self { updates } }
+}
+
+#[async_trait]
+impl Listener for Forwarder {
+    async fn handle(&mut self, command: Command, _: &Config) -> Result<()> {
+        let update = match command {
+            Command::Settings(settings) => Update::Settings(settings),
+            Command::OutputDevices(devices) => Update::OutputDevices(devices),
+            _ => return Ok(()),
+        };
+        // the tray failing to start was already logged
+        let _ = self.updates.send(update);
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str { "tray" }
+}
+
+pub fn start(message_sender: MessageSender, config: Arc<Config>, updates: glib::Receiver<Update>) -> Result<()> {
     // initialize gtk
     gtk::init().context("failed to initialize gtk")?;
 
     // create tray
-    self::create(message_sender, config)?;
+    let tray = self::create(message_sender, config);
+    updates.attach(None, move |update| {
+        tray.update(&update);
+        glib::Continue(true)
+    });
 
     // start gtk event loop
     gtk::main();
@@ -21,35 +58,249 @@ pub fn start(message_sender: MessageSender, config: Arc<Config>) -> Result<()> {
     Ok(())
 }
 
-pub fn create(message_sender: MessageSender, config: Arc<Config>) -> Result<()> {
-    let mut tray = TrayItem::new(
-        "MusicBee Media Controls",
-        IconSource::Resource("musicbee-linux-mediakeys-light")
-    )?;
+/// The parts of the menu that follow the player
+struct Tray {
+    // the indicator goes away when it's dropped
+    _indicator: AppIndicator,
+    sender: MessageSender,
+    muted: gtk::CheckMenuItem,
+    stop_after_current: gtk::CheckMenuItem,
+    auto_dj: gtk::CheckMenuItem,
+    equaliser: gtk::CheckMenuItem,
+    dsp: gtk::CheckMenuItem,
+    crossfade: gtk::CheckMenuItem,
+    replay_gain: Vec<(ReplayGainMode, gtk::RadioMenuItem)>,
+    outputs: gtk::Menu,
+    /// Set while the menu is changed to match the player,
+    /// so that the items don't send the change back as an action
+    updating: Rc<Cell<bool>>,
+}
 
-    tray.add_label("MusicBee Media Controls")?;
+impl Tray {
+    fn update(&self, update: &Update) {
+        self.updating.set(true);
+        match update {
+            Update::Settings(settings) => {
+                self.muted.set_active(settings.muted);
+                self.stop_after_current.set_active(settings.stop_after_current);
+                self.auto_dj.set_active(settings.auto_dj);
+                self.equaliser.set_active(settings.equaliser);
+                self.dsp.set_active(settings.dsp);
+                self.crossfade.set_active(settings.crossfade);
+                // checking one unchecks the rest
+                let active = self.replay_gain.iter().find(|(mode, _)| *mode == settings.replay_gain);
+                if let Some((_, item)) = active {
+                    item.set_active(true);
+                }
+            },
+            Update::OutputDevices(devices) => self.set_output_devices(devices),
+        }
+        self.updating.set(false);
+    }
+
+    fn set_output_devices(&self, devices: &OutputDevices) {
+        self.outputs.foreach(|item| self.outputs.remove(item));
+
+        let mut group = None;
+        for device in &devices.devices {
+            let sender = self.sender.clone();
+            let action = Action::OutputDevice(device.clone());
+            let item = add_radio(&self.outputs, device, group.as_ref(), &self.updating, move || sender.action(action.clone()));
+            item.set_active(devices.active.as_ref() == Some(device));
+            group.get_or_insert(item);
+        }
+
+        self.outputs.show_all();
+    }
+}
+
+fn create(message_sender: MessageSender, config: Arc<Config>) -> Tray {
+    let title = match &config.instance {
+        Some(instance) => format!("MusicBee Media Controls ({instance})"),
+        None => "MusicBee Media Controls".to_owned(),
+    };
+    let mut indicator = AppIndicator::new(&title, "musicbee-linux-mediakeys-light");
+    indicator.set_status(AppIndicatorStatus::Active);
+
+    let mut menu = gtk::Menu::new();
+    let updating = Rc::new(Cell::new(false));
+
+    let label = gtk::MenuItem::with_label(&title);
+    label.set_sensitive(false);
+    menu.append(&label);
+    menu.append(&gtk::SeparatorMenuItem::new());
 
-    // i wish i could add separators here
-    // and also mutate the label names
-    // TODO: make the tray look nicer
-    
     {
         let message_sender = message_sender.clone();
-        tray.add_menu_item("Attach", move || message_sender.attach())?;
+        add_item(&menu, "Attach", move || message_sender.attach());
     }
 
     {
         let message_sender = message_sender.clone();
-        tray.add_menu_item("Detach", move || message_sender.detach())?;
+        add_item(&menu, "Detach", move || message_sender.detach());
     }
 
     {
         let message_sender = message_sender.clone();
-        tray.add_menu_item("Refresh", move || message_sender.update())?;
+        add_item(&menu, "Refresh", move || message_sender.update());
     }
 
-    tray.add_menu_item("Show Logs", move || logger::open(&config))?;
-    tray.add_menu_item("Quit", move || message_sender.exit())?;
+    menu.append(&gtk::SeparatorMenuItem::new());
 
-    Ok(())
+    {
+        let message_sender = message_sender.clone();
+        add_item(&menu, "Love / Unlove", move || message_sender.action(Action::ToggleLove));
+    }
+
+    let ratings = gtk::Menu::new();
+    for stars in 1..=5 {
+        let message_sender = message_sender.clone();
+        let label = "★".repeat(stars.into());
+        add_item(&ratings, &label, move || message_sender.action(Action::Rate(stars)));
+    }
+    add_submenu(&menu, "Rate", &ratings);
+
+    menu.append(&gtk::SeparatorMenuItem::new());
+
+    let muted = {
+        let message_sender = message_sender.clone();
+        add_check(&menu, "Mute", &updating, move |on| message_sender.action(Action::Mute(switch(on))))
+    };
+    let stop_after_current = {
+        let message_sender = message_sender.clone();
+        add_check(&menu, "Stop After Current", &updating, move |on| message_sender.action(Action::StopAfterCurrent(switch(on))))
+    };
+    let auto_dj = {
+        let message_sender = message_sender.clone();
+        add_check(&menu, "AutoDJ", &updating, move |on| message_sender.action(Action::AutoDj(switch(on))))
+    };
+
+    menu.append(&gtk::SeparatorMenuItem::new());
+
+    let equaliser = {
+        let message_sender = message_sender.clone();
+        add_check(&menu, "Equaliser", &updating, move |on| message_sender.action(Action::Equaliser(switch(on))))
+    };
+    let dsp = {
+        let message_sender = message_sender.clone();
+        add_check(&menu, "DSP Effects", &updating, move |on| message_sender.action(Action::Dsp(switch(on))))
+    };
+    let crossfade = {
+        let message_sender = message_sender.clone();
+        add_check(&menu, "Crossfade", &updating, move |on| message_sender.action(Action::Crossfade(switch(on))))
+    };
+
+    let replay_gain_modes = gtk::Menu::new();
+    let mut replay_gain: Vec<(ReplayGainMode, gtk::RadioMenuItem)> = Vec::new();
+    for (label, mode) in [
+        ("Off", ReplayGainMode::Off),
+        ("Track", ReplayGainMode::Track),
+        ("Album", ReplayGainMode::Album),
+        ("Smart", ReplayGainMode::Smart),
+    ] {
+        let message_sender = message_sender.clone();
+        let group = replay_gain.first().map(|(_, item)| item);
+        let item = add_radio(&replay_gain_modes, label, group, &updating, move || message_sender.action(Action::ReplayGain(mode)));
+        replay_gain.push((mode, item));
+    }
+    add_submenu(&menu, "ReplayGain", &replay_gain_modes);
+
+    menu.append(&gtk::SeparatorMenuItem::new());
+
+    let player_actions = [
+        ("Next Album", Action::NextAlbum),
+        ("Previous Album", Action::PreviousAlbum),
+        ("Queue 10 Random Tracks", Action::QueueRandom(10)),
+    ];
+
+    for (label, action) in player_actions {
+        let message_sender = message_sender.clone();
+        add_item(&menu, label, move || message_sender.action(action.clone()));
+    }
+
+    // filled in once the plugin sends the devices
+    let outputs = gtk::Menu::new();
+    add_submenu(&menu, "Output Device", &outputs);
+
+    menu.append(&gtk::SeparatorMenuItem::new());
+
+    add_item(&menu, "Show Logs", move || logger::open(&config));
+    {
+        let message_sender = message_sender.clone();
+        add_item(&menu, "Quit", move || message_sender.exit());
+    }
+
+    indicator.set_menu(&mut menu);
+    menu.show_all();
+
+    Tray {
+        _indicator: indicator,
+        sender: message_sender,
+        muted,
+        stop_after_current,
+        auto_dj,
+        equaliser,
+        dsp,
+        crossfade,
+        replay_gain,
+        outputs,
+        updating,
+    }
+}
+
+fn add_item(menu: &gtk::Menu, label: &str, callback: impl Fn() + 'static) {
+    let item = gtk::MenuItem::with_label(label);
+    item.connect_activate(move |_| callback());
+    menu.append(&item);
+}
+
+fn add_submenu(menu: &gtk::Menu, label: &str, submenu: &gtk::Menu) {
+    let item = gtk::MenuItem::with_label(label);
+    item.set_submenu(Some(submenu));
+    menu.append(&item);
+}
+
+/// Adds an item that's checked when the player setting is on
+///
+/// The callback gets whether the setting should be turned on
+fn add_check(menu: &gtk::Menu, label: &str, updating: &Rc<Cell<bool>>, callback: impl Fn(bool) + 'static) -> gtk::CheckMenuItem {
+    let item = gtk::CheckMenuItem::with_label(label);
+    let updating = updating.clone();
+    item.connect_activate(move |item| {
+        if !updating.get() {
+            callback(item.is_active());
+        }
+    });
+    menu.append(&item);
+    item
+}
+
+/// Adds an item to a group where only one item is checked, joining the group's first item
+///
+/// The callback is only called when the item gets checked
+fn add_radio(
+    menu: &gtk::Menu,
+    label: &str,
+    group: Option<&gtk::RadioMenuItem>,
+    updating: &Rc<Cell<bool>>,
+    callback: impl Fn() + 'static,
+) -> gtk::RadioMenuItem {
+    let item = gtk::RadioMenuItem::with_label(label);
+    if group.is_some() {
+        item.join_group(group);
+    }
+
+    let updating = updating.clone();
+    // the item that gets unchecked toggles as well
+    item.connect_toggled(move |item| {
+        if item.is_active() && !updating.get() {
+            callback();
+        }
+    });
+    menu.append(&item);
+    item
+}
+
+const fn switch(on: bool) -> Switch {
+    if on { Switch::On } else { Switch::Off }
 }
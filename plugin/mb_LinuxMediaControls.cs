@@ -4,9 +4,16 @@ using System.Runtime.InteropServices;
 using System.Drawing;
 using System.Windows.Forms;
 using System.Collections.Generic;
+using System.Linq;
 using System.Text.RegularExpressions;
 using System.Runtime.Serialization;
+using System.Security;
 using System.Runtime.Serialization.Formatters.Soap;
+using System.Net;
+using System.Net.Sockets;
+using System.Text;
+using System.Threading;
+using System.Web.Script.Serialization;
 
 namespace MusicBeePlugin
 {
@@ -28,8 +35,21 @@ namespace MusicBeePlugin
         private ConfigPanel panel;
 
         private float volume = 1;
+        // how much of the now playing list is sent around the playing track
+        private const int queueBefore = 5;
+        private const int queueAfter = 20;
         public bool ignoreNextVolumeUpdate = false;
 
+        // where each podcast episode is, as the subscription id and the episode's index,
+        // by its url and by its show and title. looking through every subscription is slow,
+        // so it's only done every so often
+        private Dictionary<string, Tuple<string, int, string>> episodesByUrl = new Dictionary<string, Tuple<string, int, string>>();
+        private Dictionary<string, Tuple<string, int, string>> episodesByTitle = new Dictionary<string, Tuple<string, int, string>>();
+        private DateTime episodesIndexed = DateTime.MinValue;
+        private static readonly TimeSpan episodeIndexLifetime = TimeSpan.FromMinutes(10);
+        // episodes that were just downloaded or subscribed to can make the index rebuild early, but not this often
+        private static readonly TimeSpan episodeIndexMinimumAge = TimeSpan.FromMinutes(1);
+
         public PluginInfo Initialise(IntPtr apiInterfacePtr)
         {
             mbApiInterface = new MusicBeeApiInterface();
@@ -45,8 +65,8 @@ namespace MusicBeePlugin
             about.Revision = 1;
             about.MinInterfaceVersion = MinInterfaceVersion;
             about.MinApiRevision = MinApiRevision;
-            about.ReceiveNotifications = (ReceiveNotificationFlags.PlayerEvents | ReceiveNotificationFlags.TagEvents);
-            about.ConfigurationPanelHeight = 20;   // height in pixels that musicbee should reserve in a panel for config settings. When set, a handle to an empty panel will be passed to the Configure function
+            about.ReceiveNotifications = (ReceiveNotificationFlags.PlayerEvents | ReceiveNotificationFlags.TagEvents | ReceiveNotificationFlags.DownloadEvents);
+            about.ConfigurationPanelHeight = 45;   // height in pixels that musicbee should reserve in a panel for config settings. When set, a handle to an empty panel will be passed to the Configure function
 
             Directory.CreateDirectory(this.ConfigDirectory);
             this.config = this.getConfig();
@@ -77,12 +97,14 @@ namespace MusicBeePlugin
         {
             this.config = this.panel.update();
             this.saveConfig(this.config);
+            this.communication.reconnect(this.config);
         }
 
         // MusicBee is closing the plugin (plugin is being disabled by user or MusicBee is shutting down)
         public void Close(PluginCloseReason reason)
         {
             this.Deactivate();
+            this.communication.disconnect();
         }
 
         // uninstall this plugin - clean up any persisted files
@@ -100,14 +122,46 @@ namespace MusicBeePlugin
                     this.UpdateVolume();
                     this.UpdatePlayback();
                     this.UpdateMetaData();
+                    this.UpdateOutputDevices();
+                    this.UpdateQueue();
+                    this.UpdatePlaylists();
+                    this.UpdateLyrics();
                     this.Activate();
+                    this.communication.connect();
                     break;
                 case NotificationType.TrackChanged:
                 // TODO: this doesn't do the right thing, find some other event for when the current file's tags are changed
                 /* case NotificationType.TagsChanged: // dunno if this does anything but might as well */
+                    this.UpdateMetaData();
+                    // the handler's clock starts over with the new track
+                    this.UpdatePlayback();
+                    this.UpdateQueue();
+                    this.UpdateLyrics();
+                    break;
+                case NotificationType.NowPlayingLyricsReady:
+                    this.UpdateLyrics();
+                    break;
+                case NotificationType.PlayingTracksChanged:
+                case NotificationType.NowPlayingListEnded:
+                    this.UpdateQueue();
+                    break;
+                case NotificationType.PlaylistCreated:
+                case NotificationType.PlaylistUpdated:
+                case NotificationType.PlaylistDeleted:
+                    this.UpdatePlaylists();
+                    break;
+                case NotificationType.RatingChanged:
                     this.UpdateMetaData();
                     break;
                 case NotificationType.PlayStateChanged:
+                case NotificationType.PlayerShuffleChanged:
+                case NotificationType.PlayerRepeatChanged:
+                case NotificationType.VolumeMuteChanged:
+                case NotificationType.StopAfterCurrentChanged:
+                case NotificationType.AutoDjStarted:
+                case NotificationType.AutoDjStopped:
+                case NotificationType.PlayerEqualiserOnOffChanged:
+                case NotificationType.ReplayGainChanged:
                     this.UpdatePlayback();
                     break;
                 case NotificationType.VolumeLevelChanged:
@@ -154,14 +208,71 @@ namespace MusicBeePlugin
             }
         }
 
+        // actions coming from the socket didn't need to change the volume to get here
+        private void RecieveSocketCommand(string action)
+        {
+            if(this.communication.performAction(action) == VolumeUpdate.SendFromStored) {
+                this.ResetVolume();
+                this.SendVolume();
+            }
+        }
+
+        // sends over everything the handler needs to know
+        private void SendState()
+        {
+            this.UpdateVolume();
+            this.UpdatePlayback();
+            this.UpdateMetaData();
+            this.UpdateOutputDevices();
+            this.UpdateQueue();
+            this.UpdatePlaylists();
+            this.UpdateLyrics();
+        }
+
+        // runs the callback on musicbee's ui thread
+        private void Invoke(Action callback)
+        {
+            Control window = Control.FromHandle(mbApiInterface.MB_GetWindowHandle());
+            if(window == null)
+                callback();
+            else
+                window.BeginInvoke(callback);
+        }
+
+        // brings musicbee's window to the front, even when it's minimised
+        private void RaiseWindow()
+        {
+            this.Invoke(() => {
+                Form window = Control.FromHandle(mbApiInterface.MB_GetWindowHandle()) as Form;
+                if(window == null)
+                    return;
+
+                if(window.WindowState == FormWindowState.Minimized)
+                    window.WindowState = FormWindowState.Normal;
+                window.Show();
+                window.Activate();
+            });
+        }
+
+        // closes musicbee the same way as clicking the close button
+        private void CloseWindow()
+        {
+            this.Invoke(() => {
+                Form window = Control.FromHandle(mbApiInterface.MB_GetWindowHandle()) as Form;
+                if(window != null)
+                    window.Close();
+            });
+        }
+
         private float GetPlayerVolume() 
             { return mbApiInterface.Player_GetVolume(); }
 
         private void ResetVolume() 
         {
-            this.ignoreNextVolumeUpdate = true;
-            if(Math.Abs(this.GetPlayerVolume() - this.volume) > 0.01)
+            if(Math.Abs(this.GetPlayerVolume() - this.volume) > 0.01) {
+                this.ignoreNextVolumeUpdate = true;
                 mbApiInterface.Player_SetVolume(this.volume);
+            }
         }
 
         private void UpdateVolume() 
@@ -173,7 +284,7 @@ namespace MusicBeePlugin
         private void UpdateInternalVolume() 
             { this.volume = this.GetPlayerVolume(); }
         private void SendVolume() 
-            { this.communication.write(Communication.volumeFile, "" + this.volume); }
+            { this.communication.publish(Communication.volumeFile, "" + this.volume); }
 
         private void CreateFileStructure()
         {
@@ -183,16 +294,22 @@ namespace MusicBeePlugin
             File.Create(this.config.rootDirectory + Communication.metadataFile).Close();
             File.Create(this.config.rootDirectory + Communication.actionFile).Close();
             File.Create(this.config.rootDirectory + Communication.volumeFile).Close();
+            File.Create(this.config.rootDirectory + Communication.outputDevicesFile).Close();
+            File.Create(this.config.rootDirectory + Communication.queueFile).Close();
+            File.Create(this.config.rootDirectory + Communication.playlistsFile).Close();
+            File.Create(this.config.rootDirectory + Communication.searchResultsFile).Close();
+            File.Create(this.config.rootDirectory + Communication.lyricsFile).Close();
+            File.Create(this.config.rootDirectory + Communication.podcastsFile).Close();
         }
 
         private void Activate()
         {
-            File.WriteAllText(this.config.rootDirectory + Communication.activatedFile, "true");
+            this.communication.publish(Communication.activatedFile, "true");
         }
 
         private void Deactivate()
         {
-            File.WriteAllText(this.config.rootDirectory + Communication.activatedFile, "false");
+            this.communication.publish(Communication.activatedFile, "false");
         }
 
         private void UpdatePlayback() 
@@ -218,10 +335,58 @@ namespace MusicBeePlugin
             }
 
             int position = mbApiInterface.Player_GetPosition();
+            bool shuffle = mbApiInterface.Player_GetShuffle();
+            string repeat = null;
+
+            switch (mbApiInterface.Player_GetRepeat())
+            {
+                case RepeatMode.One:
+                    repeat = "one";
+                    break;
+                case RepeatMode.All:
+                    repeat = "all";
+                    break;
+                default:
+                    repeat = "none";
+                    break;
+            }
 
-            this.communication.write(Communication.playbackFile,
+            bool muted = mbApiInterface.Player_GetMute();
+            bool stopAfterCurrent = mbApiInterface.Player_GetStopAfterCurrentEnabled();
+            bool autoDj = mbApiInterface.Player_GetAutoDjEnabled();
+            bool equaliser = mbApiInterface.Player_GetEqualiserEnabled();
+            bool dsp = mbApiInterface.Player_GetDspEnabled();
+            bool crossfade = mbApiInterface.Player_GetCrossfade();
+            string replayGain = null;
+
+            switch (mbApiInterface.Player_GetReplayGainMode())
+            {
+                case ReplayGainMode.Track:
+                    replayGain = "track";
+                    break;
+                case ReplayGainMode.Album:
+                    replayGain = "album";
+                    break;
+                case ReplayGainMode.Smart:
+                    replayGain = "smart";
+                    break;
+                default:
+                    replayGain = "off";
+                    break;
+            }
+
+            this.communication.publish(Communication.playbackFile,
                 state + "\n" +
-                position
+                position + "\n" +
+                (shuffle ? "true" : "false") + "\n" +
+                repeat + "\n" +
+                (muted ? "true" : "false") + "\n" +
+                (stopAfterCurrent ? "true" : "false") + "\n" +
+                (autoDj ? "true" : "false") + "\n" +
+                (equaliser ? "true" : "false") + "\n" +
+                (dsp ? "true" : "false") + "\n" +
+                (crossfade ? "true" : "false") + "\n" +
+                replayGain
             );
         }
 
@@ -232,13 +397,213 @@ namespace MusicBeePlugin
             string artist = mbApiInterface.NowPlaying_GetFileTag(MetaDataType.Artist);
             string cover = mbApiInterface.NowPlaying_GetArtworkUrl();
             int duration = mbApiInterface.NowPlaying_GetDuration();
+            string albumArtist = mbApiInterface.NowPlaying_GetFileTag(MetaDataType.AlbumArtist);
+            // seperated by null characters
+            string artists = mbApiInterface.NowPlaying_GetFileTag(MetaDataType.MultiArtist);
+            string trackNumber = mbApiInterface.NowPlaying_GetFileTag(MetaDataType.TrackNo);
+            string discNumber = mbApiInterface.NowPlaying_GetFileTag(MetaDataType.DiscNo);
+            string genres = Communication.multiValue(mbApiInterface.NowPlaying_GetFileTag(MetaDataType.Genre));
+            string file = mbApiInterface.NowPlaying_GetFileUrl();
+            // out of 5 stars, empty when it's unrated
+            string rating = mbApiInterface.NowPlaying_GetFileTag(MetaDataType.Rating);
+            bool loved = mbApiInterface.NowPlaying_GetFileTag(MetaDataType.RatingLove).StartsWith("L");
+
+            // podcast episodes get their titles and art from the subscription
+            string podcast = "";
+            string description = "";
+            string subscriptionId;
+            string[] subscription;
+            string[] episode;
+            if(this.FindEpisode(file, album, title, out subscriptionId, out subscription, out episode)) {
+                podcast = subscription[(int)SubscriptionMetaDataType.Title];
+                title = episode[(int)EpisodeMetaDataType.Title];
+                album = podcast;
+                description = episode[(int)EpisodeMetaDataType.Description];
+                if(string.IsNullOrEmpty(cover))
+                    cover = this.SavePodcastArtwork(subscriptionId);
+            }
 
-            this.communication.write(Communication.metadataFile,
+            this.communication.publish(Communication.metadataFile,
                 title + "\n" +
                 album + "\n" +
                 artist + "\n" +
                 cover + "\n" +
-                duration
+                duration + "\n" +
+                albumArtist + "\n" +
+                artists + "\n" +
+                trackNumber + "\n" +
+                discNumber + "\n" +
+                genres + "\n" +
+                file + "\n" +
+                rating + "\n" +
+                (loved ? "true" : "false") + "\n" +
+                podcast + "\n" +
+                // the description can go over multiple lines, so it's last
+                description
+            );
+        }
+
+        // finds the podcast episode that's playing, either by its url or, for downloaded episodes, by its show and title
+        private bool FindEpisode(string file, string album, string title, out string subscriptionId, out string[] subscription, out string[] episode)
+        {
+            subscriptionId = null;
+            subscription = null;
+            episode = null;
+
+            // library tracks can't be episodes, even if their album and title match one
+            bool downloaded = this.config.InPodcastFolder(file);
+            if(!downloaded && !file.Contains("://"))
+                return false;
+
+            if(DateTime.Now - this.episodesIndexed > episodeIndexLifetime)
+                this.IndexEpisodes();
+
+            Tuple<string, int, string> found = this.LookUpEpisode(file, album, title, downloaded);
+            if(found == null && DateTime.Now - this.episodesIndexed > episodeIndexMinimumAge) {
+                this.IndexEpisodes();
+                found = this.LookUpEpisode(file, album, title, downloaded);
+            }
+            if(found == null)
+                return false;
+
+            if(!mbApiInterface.Podcasts_GetSubscription(found.Item1, out subscription) || !mbApiInterface.Podcasts_GetSubscriptionEpisode(found.Item1, found.Item2, out episode))
+                return false;
+            // the episodes moved around since they were indexed
+            if(episode[(int)EpisodeMetaDataType.Title] != found.Item3) {
+                this.episodesIndexed = DateTime.MinValue;
+                return false;
+            }
+
+            subscriptionId = found.Item1;
+            return true;
+        }
+
+        private Tuple<string, int, string> LookUpEpisode(string file, string album, string title, bool downloaded)
+        {
+            Tuple<string, int, string> found;
+            if(this.episodesByUrl.TryGetValue(file, out found))
+                return found;
+            // downloaded episodes are played from a file, but they keep the show as the album
+            if(downloaded && this.episodesByTitle.TryGetValue(album + "\t" + title, out found))
+                return found;
+            return null;
+        }
+
+        private void IndexEpisodes()
+        {
+            this.episodesByUrl.Clear();
+            this.episodesByTitle.Clear();
+            this.episodesIndexed = DateTime.Now;
+
+            string[] ids;
+            if(!mbApiInterface.Podcasts_QuerySubscriptions(null, out ids))
+                return;
+
+            foreach(string id in ids) {
+                string[] subscription;
+                string[] urls;
+                if(!mbApiInterface.Podcasts_GetSubscription(id, out subscription) || !mbApiInterface.Podcasts_GetSubscriptionEpisodes(id, out urls))
+                    continue;
+
+                string show = subscription[(int)SubscriptionMetaDataType.Title];
+                for(int i = 0; i < urls.Length; i++) {
+                    string[] episode;
+                    if(!mbApiInterface.Podcasts_GetSubscriptionEpisode(id, i, out episode))
+                        continue;
+
+                    string title = episode[(int)EpisodeMetaDataType.Title];
+                    Tuple<string, int, string> found = Tuple.Create(id, i, title);
+                    this.episodesByUrl[urls[i]] = found;
+                    this.episodesByTitle[show + "\t" + title] = found;
+                }
+            }
+        }
+
+        // musicbee only gives the artwork as bytes, so it's saved in the temp folder for the handler
+        private string SavePodcastArtwork(string subscriptionId)
+        {
+            byte[] artwork;
+            if(!mbApiInterface.Podcasts_GetSubscriptionArtwork(subscriptionId, 0, out artwork) || artwork == null)
+                return "";
+
+            string name = "mb_podcast_" + string.Concat(subscriptionId.Where(Char.IsLetterOrDigit)) + ".jpg";
+            string path = Path.Combine(Path.GetTempPath(), name);
+            try {
+                File.WriteAllBytes(path, artwork);
+            } catch (IOException) {
+                return "";
+            }
+            return path;
+        }
+
+        // the active device on the first line, then every device
+        private void UpdateOutputDevices()
+        {
+            string[] devices;
+            string active;
+            if(!mbApiInterface.Player_GetOutputDevices(out devices, out active))
+                return;
+
+            this.communication.publish(Communication.outputDevicesFile,
+                active + "\n" + String.Join("\n", devices)
+            );
+        }
+
+        // the current index on the first line, then the tracks around it,
+        // one per line as the index, title, artist, album and file seperated by tabs
+        private void UpdateQueue()
+        {
+            int current = mbApiInterface.NowPlayingList_GetCurrentIndex();
+            string[] files;
+            if(!mbApiInterface.NowPlayingList_QueryFilesEx(null, out files))
+                files = new string[0];
+
+            MetaDataType[] fields = { MetaDataType.TrackTitle, MetaDataType.Artist, MetaDataType.Album };
+            StringBuilder queue = new StringBuilder();
+            queue.Append(current);
+
+            int start = Math.Max(0, current - queueBefore);
+            int end = Math.Min(files.Length, Math.Max(0, current) + queueAfter + 1);
+            for(int index = start; index < end; index++) {
+                string[] tags;
+                if(!mbApiInterface.NowPlayingList_GetFileTags(index, fields, out tags))
+                    continue;
+
+                queue.Append("\n" + index);
+                foreach(string tag in tags)
+                    queue.Append("\t" + tag.Replace('\t', ' ').Replace('\n', ' '));
+                queue.Append("\t" + files[index]);
+            }
+
+            this.communication.publish(Communication.queueFile, queue.ToString());
+        }
+
+        // one playlist per line as the url and name seperated by a tab
+        private void UpdatePlaylists()
+        {
+            StringBuilder playlists = new StringBuilder();
+
+            if(mbApiInterface.Playlist_QueryPlaylists()) {
+                string url;
+                while((url = mbApiInterface.Playlist_QueryGetNextPlaylist()) != null) {
+                    string name = mbApiInterface.Playlist_GetName(url);
+                    playlists.Append(url + "\t" + name.Replace('\t', ' ').Replace('\n', ' ') + "\n");
+                }
+            }
+
+            this.communication.publish(Communication.playlistsFile, playlists.ToString());
+        }
+
+        // the file on the first line, so the handler can look for an .lrc next to it, then the lyrics
+        private void UpdateLyrics()
+        {
+            string lyrics = mbApiInterface.NowPlaying_GetLyrics();
+            if(string.IsNullOrEmpty(lyrics))
+                lyrics = mbApiInterface.NowPlaying_GetDownloadedLyrics();
+
+            this.communication.publish(Communication.lyricsFile,
+                mbApiInterface.NowPlaying_GetFileUrl() + "\n" +
+                lyrics
             );
         }
 
@@ -265,10 +630,26 @@ namespace MusicBeePlugin
             public const string activatedFile = "plugin-activated";
             public const string actionFile = "action";
             public const string volumeFile = "volume";
+            public const string outputDevicesFile = "output-devices";
+            public const string queueFile = "queue";
+            public const string playlistsFile = "playlists";
+            public const string searchResultsFile = "search-results";
+            public const string lyricsFile = "lyrics";
+            public const string podcastsFile = "podcasts";
+            public const string socketTokenFile = "socket-token";
+
+            // the last line of an answer to a request, so the handler knows it isn't still being written
+            public const string answerEnd = "end";
+
+            // separates a tag's values with null characters like MultiArtist, since MusicBee shows genres joined by "; "
+            public static string multiValue(string tag) {
+                return (tag ?? "").Replace("; ", "\0");
+            }
 
             private Config config;
             private MusicBeeApiInterface mbApiInterface;
             private Plugin plugin;
+            private SocketConnection socket;
 
             public Communication(Config config, MusicBeeApiInterface mbApiInterface, Plugin plugin) {
                 this.config = config;
@@ -276,10 +657,52 @@ namespace MusicBeePlugin
                 this.plugin = plugin;
             }
 
+            // starts talking to the handler's socket if one is configured
+            public void connect() {
+                if(this.config.socketPort == 0 || this.socket != null)
+                    return;
+
+                Plugin plugin = this.plugin;
+                Config config = this.config;
+                this.socket = new SocketConnection(
+                    this.config.socketPort,
+                    () => File.ReadAllText(config.rootDirectory + Communication.socketTokenFile).Trim(),
+                    () => plugin.Invoke(plugin.Activate),
+                    () => plugin.Invoke(plugin.SendState),
+                    action => plugin.Invoke(() => plugin.RecieveSocketCommand(action))
+                );
+                this.socket.start();
+            }
+
+            public void disconnect() {
+                if(this.socket == null)
+                    return;
+
+                this.socket.stop();
+                this.socket = null;
+            }
+
+            public void reconnect(Config config) {
+                this.disconnect();
+                this.config = config;
+                this.connect();
+            }
+
             public void write(string file, string text) {
+                // the directory might have been cleaned up while musicbee was running
+                Directory.CreateDirectory(this.config.rootDirectory);
                 File.WriteAllText(this.config.rootDirectory + file, text); 
             }
 
+            // writes the file and sends it over the socket if it's connected
+            public void publish(string file, string text) {
+                this.write(file, text);
+
+                SocketConnection socket = this.socket;
+                if(socket != null)
+                    socket.send(file, text);
+            }
+
             public string get(string file) {
                 return File.ReadAllText(this.config.rootDirectory + file);
             }
@@ -290,7 +713,15 @@ namespace MusicBeePlugin
                 if(string.IsNullOrWhiteSpace(action)) 
                     return VolumeUpdate.SendFromPlayer;
 
-                string[] args = action.Trim().Split();
+                VolumeUpdate volumeUpdate = this.performAction(action);
+
+                write(Communication.actionFile, "");
+                return volumeUpdate;
+            }
+
+            public VolumeUpdate performAction(string action) {
+                // the argument is the rest of the line, since device names have spaces
+                string[] args = action.Trim().Split(new char[] { ' ' }, 2);
 
                 // no-arg commands
                 switch(args[0]) {
@@ -301,6 +732,24 @@ namespace MusicBeePlugin
                         this.plugin.UpdateInternalVolume();
                         this.plugin.SendVolume();
                         break;
+                    case "raise":
+                        this.plugin.RaiseWindow();
+                        break;
+                    case "quit":
+                        this.plugin.CloseWindow();
+                        break;
+                    case "love":
+                        this.toggleLove();
+                        break;
+                    case "next_album":
+                        mbApiInterface.Player_PlayNextAlbum();
+                        break;
+                    case "previous_album":
+                        mbApiInterface.Player_PlayPreviousAlbum();
+                        break;
+                    case "queue_clear":
+                        mbApiInterface.NowPlayingList_Clear();
+                        break;
                 }
 
                 VolumeUpdate volumeUpdate = VolumeUpdate.PlayerFromStored;
@@ -318,6 +767,7 @@ namespace MusicBeePlugin
                             break;
                         case "seek":
                             this.seek(args[1]);
+                            this.plugin.UpdatePlayback();
                             break;
                         case "position":
                             this.setPosition(args[1]);
@@ -327,13 +777,104 @@ namespace MusicBeePlugin
                             this.setVolume(args[1]);
                             volumeUpdate = VolumeUpdate.SendFromStored;
                             break;
+                        case "rate":
+                            this.setRating(args[1]);
+                            break;
+                        case "mute":
+                            mbApiInterface.Player_SetMute(this.switchValue(args[1], mbApiInterface.Player_GetMute()));
+                            break;
+                        case "stop_after_current":
+                            bool stopAfterCurrent = mbApiInterface.Player_GetStopAfterCurrentEnabled();
+                            // musicbee only lets it be toggled
+                            if(this.switchValue(args[1], stopAfterCurrent) != stopAfterCurrent)
+                                mbApiInterface.Player_StopAfterCurrent();
+                            break;
+                        case "auto_dj":
+                            bool autoDj = mbApiInterface.Player_GetAutoDjEnabled();
+                            bool startAutoDj = this.switchValue(args[1], autoDj);
+                            if(startAutoDj && !autoDj)
+                                mbApiInterface.Player_StartAutoDj();
+                            else if(!startAutoDj && autoDj)
+                                mbApiInterface.Player_EndAutoDj();
+                            break;
+                        case "queue_random":
+                            parseIntAnd(args[1], count => mbApiInterface.Player_QueueRandomTracks(count));
+                            break;
+                        case "equaliser":
+                            mbApiInterface.Player_SetEqualiserEnabled(this.switchValue(args[1], mbApiInterface.Player_GetEqualiserEnabled()));
+                            break;
+                        // musicbee doesn't notify when these change
+                        case "dsp":
+                            mbApiInterface.Player_SetDspEnabled(this.switchValue(args[1], mbApiInterface.Player_GetDspEnabled()));
+                            this.plugin.UpdatePlayback();
+                            break;
+                        case "crossfade":
+                            mbApiInterface.Player_SetCrossfade(this.switchValue(args[1], mbApiInterface.Player_GetCrossfade()));
+                            this.plugin.UpdatePlayback();
+                            break;
+                        case "replay_gain":
+                            this.setReplayGain(args[1]);
+                            break;
+                        case "play_index":
+                            parseIntAnd(args[1], this.playIndex);
+                            break;
+                        // files are seperated by tabs
+                        case "play_now":
+                            string[] files = args[1].Split('\t');
+                            mbApiInterface.NowPlayingList_PlayNow(files[0]);
+                            if(files.Length > 1)
+                                mbApiInterface.NowPlayingList_QueueFilesNext(files.Skip(1).ToArray());
+                            break;
+                        case "queue_next":
+                            mbApiInterface.NowPlayingList_QueueFilesNext(args[1].Split('\t'));
+                            break;
+                        case "queue_last":
+                            mbApiInterface.NowPlayingList_QueueFilesLast(args[1].Split('\t'));
+                            break;
+                        case "queue_remove":
+                            parseIntAnd(args[1], index => mbApiInterface.NowPlayingList_RemoveAt(index));
+                            break;
+                        // the index to move to, then the indices to move
+                        case "queue_move":
+                            parseIntsAnd(args[1], indices => {
+                                if(indices.Length > 1)
+                                    mbApiInterface.NowPlayingList_MoveFiles(indices.Skip(1).ToArray(), indices[0]);
+                            });
+                            break;
+                        case "search":
+                            this.search(args[1]);
+                            break;
+                        case "podcasts":
+                            this.listPodcasts(args[1]);
+                            break;
+                        case "play_playlist":
+                            mbApiInterface.Playlist_PlayNow(args[1]);
+                            break;
+                        case "output_device":
+                            if(mbApiInterface.Player_SetOutputDevice(args[1]))
+                                this.plugin.UpdateOutputDevices();
+                            break;
                     }
                 }
 
-                write(Communication.actionFile, "");
                 return volumeUpdate;
             }
 
+            // "on" / "true", "off" / "false" or "toggle"
+            private bool switchValue(string arg, bool current)
+            {
+                switch (arg)
+                {
+                    case "on":
+                    case "true":
+                        return true;
+                    case "toggle":
+                        return !current;
+                    default:
+                        return false;
+                }
+            }
+
             private void updateShuffle(string arg) 
             {
                 switch (arg)
@@ -367,6 +908,156 @@ namespace MusicBeePlugin
                 }
             }
 
+            private void setReplayGain(string arg)
+            {
+                switch (arg)
+                {
+                    case "track":
+                        mbApiInterface.Player_SetReplayGainMode(ReplayGainMode.Track);
+                        return;
+                    case "album":
+                        mbApiInterface.Player_SetReplayGainMode(ReplayGainMode.Album);
+                        return;
+                    case "smart":
+                        mbApiInterface.Player_SetReplayGainMode(ReplayGainMode.Smart);
+                        return;
+                    default: // "off"
+                        mbApiInterface.Player_SetReplayGainMode(ReplayGainMode.Off);
+                        return;
+                }
+            }
+
+            // musicbee jumps to files that are already in the now playing list
+            private void playIndex(int index)
+            {
+                string[] files;
+                if(!mbApiInterface.NowPlayingList_QueryFilesEx(null, out files) || index < 0 || index >= files.Length)
+                    return;
+
+                // musicbee can only play a file, which plays its first copy in the list,
+                // so later copies are ignored rather than playing the wrong one
+                if(Array.IndexOf(files, files[index]) != index)
+                    return;
+
+                mbApiInterface.NowPlayingList_PlayNow(files[index]);
+            }
+
+            // the request id, then pairs of fields and values, all seperated by tabs.
+            // the results go on the lines after the id, as the file and its tags seperated by tabs
+            private void search(string request)
+            {
+                string[] parts = request.Split('\t');
+                int limit = 0;
+                List<string> conditions = new List<string>();
+                List<string> words = new List<string>();
+
+                for(int i = 1; i + 1 < parts.Length; i += 2) {
+                    string value = parts[i + 1];
+                    switch (parts[i])
+                    {
+                        case "artist":
+                            conditions.Add(searchCondition("Artist", value));
+                            break;
+                        case "album":
+                            conditions.Add(searchCondition("Album", value));
+                            break;
+                        case "title":
+                            conditions.Add(searchCondition("Title", value));
+                            break;
+                        // every word has to be in the title, artist or album
+                        case "text":
+                            words.AddRange(value.ToLower().Split(new char[] { ' ' }, StringSplitOptions.RemoveEmptyEntries));
+                            break;
+                        case "limit":
+                            Int32.TryParse(value, out limit);
+                            break;
+                    }
+                }
+
+                string query = null;
+                if(conditions.Count > 0)
+                    query = "<SmartPlaylist><Source Type=\"1\"><Conditions CombineMethod=\"All\">" +
+                        String.Join("", conditions) +
+                        "</Conditions></Source></SmartPlaylist>";
+
+                string[] files;
+                if(!mbApiInterface.Library_QueryFilesEx(query, out files))
+                    files = new string[0];
+
+                MetaDataType[] fields = {
+                    MetaDataType.TrackTitle, MetaDataType.Artist, MetaDataType.Album, MetaDataType.AlbumArtist,
+                    MetaDataType.TrackNo, MetaDataType.Year, MetaDataType.Genre,
+                };
+                StringBuilder results = new StringBuilder(parts[0]);
+                int count = 0;
+
+                foreach(string file in files) {
+                    if(limit > 0 && count >= limit)
+                        break;
+
+                    string[] tags;
+                    if(!mbApiInterface.Library_GetFileTags(file, fields, out tags))
+                        continue;
+
+                    tags[6] = Communication.multiValue(tags[6]);
+                    string searched = (tags[0] + " " + tags[1] + " " + tags[2]).ToLower();
+                    if(!words.All(word => searched.Contains(word)))
+                        continue;
+
+                    results.Append("\n" + file);
+                    foreach(string tag in tags)
+                        results.Append("\t" + tag.Replace('\t', ' ').Replace('\n', ' '));
+                    count++;
+                }
+
+                results.Append("\n" + Communication.answerEnd);
+                this.write(Communication.searchResultsFile, results.ToString());
+            }
+
+            // the request id on the first line, then each subscription followed by its unplayed episodes
+            private void listPodcasts(string id)
+            {
+                StringBuilder podcasts = new StringBuilder(id);
+
+                string[] ids;
+                if(!mbApiInterface.Podcasts_QuerySubscriptions(null, out ids))
+                    ids = new string[0];
+
+                foreach(string subscriptionId in ids) {
+                    string[] subscription;
+                    string[] urls;
+                    if(!mbApiInterface.Podcasts_GetSubscription(subscriptionId, out subscription) || !mbApiInterface.Podcasts_GetSubscriptionEpisodes(subscriptionId, out urls))
+                        continue;
+
+                    podcasts.Append("\nsubscription\t" + subscriptionId + "\t" + clean(subscription[(int)SubscriptionMetaDataType.Title]));
+
+                    for(int i = 0; i < urls.Length; i++) {
+                        string[] episode;
+                        if(!mbApiInterface.Podcasts_GetSubscriptionEpisode(subscriptionId, i, out episode))
+                            continue;
+                        if(episode[(int)EpisodeMetaDataType.HasBeenPlayed].Equals("true", StringComparison.OrdinalIgnoreCase))
+                            continue;
+
+                        podcasts.Append("\nepisode\t" + urls[i]);
+                        foreach(EpisodeMetaDataType field in new[] { EpisodeMetaDataType.Title, EpisodeMetaDataType.DateTime, EpisodeMetaDataType.Duration, EpisodeMetaDataType.IsDownloaded })
+                            podcasts.Append("\t" + clean(episode[(int)field]));
+                    }
+                }
+
+                podcasts.Append("\n" + Communication.answerEnd);
+                this.write(Communication.podcastsFile, podcasts.ToString());
+            }
+
+            private static string clean(string field)
+            {
+                return (field ?? "").Replace('\t', ' ').Replace('\n', ' ');
+            }
+
+            private static string searchCondition(string field, string value)
+            {
+                return "<Condition Field=\"" + field + "\" Comparison=\"Contains\" Value=\"" + SecurityElement.Escape(value) + "\" />";
+            }
+
             private void parseIntAnd(string val, Action<int> callback) 
             {
                 try {
@@ -375,6 +1066,14 @@ namespace MusicBeePlugin
                 } catch (FormatException) {}
             }
 
+            private void parseIntsAnd(string val, Action<int[]> callback) 
+            {
+                try {
+                    callback(val.Split(' ').Select(Int32.Parse).ToArray());
+                // TODO: log
+                } catch (FormatException) {}
+            }
+
             private void seek(string str)
             {
                 parseIntAnd(str, amt => {
@@ -389,6 +1088,32 @@ namespace MusicBeePlugin
                 });
             }
 
+            // sets a tag on the playing track and saves it to the file
+            private void setTag(MetaDataType field, string value)
+            {
+                string file = mbApiInterface.NowPlaying_GetFileUrl();
+                if(String.IsNullOrEmpty(file))
+                    return;
+
+                mbApiInterface.Library_SetFileTag(file, field, value);
+                mbApiInterface.Library_CommitTagsToFile(file);
+                this.plugin.UpdateMetaData();
+            }
+
+            private void setRating(string str)
+            {
+                parseIntAnd(str, stars => {
+                    // no stars removes the rating
+                    this.setTag(MetaDataType.Rating, stars <= 0 ? "" : Math.Min(stars, 5).ToString());
+                });
+            }
+
+            private void toggleLove()
+            {
+                bool loved = mbApiInterface.NowPlaying_GetFileTag(MetaDataType.RatingLove).StartsWith("L");
+                this.setTag(MetaDataType.RatingLove, loved ? "" : "L");
+            }
+
             private void setVolume(string str)
             {
                 parseIntAnd(str, to => {
@@ -397,6 +1122,131 @@ namespace MusicBeePlugin
                 });
             }
         }
+
+        // talks to the handler over a localhost socket, one line of json per frame
+        class SocketConnection {
+            private const int retryDelay = 5000;
+
+            private int port;
+            private Func<string> readToken;
+            private Action onConnect;
+            private Action onUpdate;
+            private Action<string> onAction;
+
+            private JavaScriptSerializer serializer = new JavaScriptSerializer();
+            private object writeLock = new object();
+            private TcpClient client;
+            private StreamWriter writer;
+            private volatile bool running;
+
+            public SocketConnection(int port, Func<string> readToken, Action onConnect, Action onUpdate, Action<string> onAction) {
+                this.port = port;
+                this.readToken = readToken;
+                this.onConnect = onConnect;
+                this.onUpdate = onUpdate;
+                this.onAction = onAction;
+            }
+
+            public void start() {
+                this.running = true;
+                new Thread(this.run) { IsBackground = true }.Start();
+            }
+
+            public void stop() {
+                this.running = false;
+                lock(this.writeLock) {
+                    if(this.client != null)
+                        this.client.Close();
+                }
+            }
+
+            public void send(string file, string text) {
+                string frame = this.serializer.Serialize(new Dictionary<string, object> {
+                    { "type", "state" },
+                    { "name", file },
+                    { "contents", text },
+                });
+
+                lock(this.writeLock) {
+                    if(this.writer == null)
+                        return;
+
+                    // if this fails the connection is broken,
+                    // which the reading thread will notice and reconnect
+                    try {
+                        this.writer.Write(frame + "\n");
+                        this.writer.Flush();
+                    } catch (IOException) {
+                    } catch (ObjectDisposedException) {}
+                }
+            }
+
+            private void run() {
+                while(this.running) {
+                    try {
+                        // the handler writes a new token each time it starts, and
+                        // drops connections that don't send it first
+                        string token = this.readToken();
+
+                        TcpClient client = new TcpClient();
+                        client.Connect(IPAddress.Loopback, this.port);
+                        StreamReader reader = new StreamReader(client.GetStream(), new UTF8Encoding(false));
+                        StreamWriter writer = new StreamWriter(client.GetStream(), new UTF8Encoding(false));
+                        writer.Write(this.serializer.Serialize(new Dictionary<string, object> {
+                            { "type", "hello" },
+                            { "token", token },
+                        }) + "\n");
+                        writer.Flush();
+
+                        lock(this.writeLock) {
+                            this.client = client;
+                            this.writer = writer;
+                        }
+
+                        this.onConnect();
+
+                        string line;
+                        while((line = reader.ReadLine()) != null)
+                            this.receive(line);
+                    // TODO: log
+                    } catch (SocketException) {
+                    } catch (IOException) {
+                    } catch (ObjectDisposedException) {}
+
+                    lock(this.writeLock) {
+                        if(this.client != null)
+                            this.client.Close();
+                        this.client = null;
+                        this.writer = null;
+                    }
+
+                    if(this.running)
+                        Thread.Sleep(retryDelay);
+                }
+            }
+
+            private void receive(string line) {
+                Dictionary<string, object> frame;
+                try {
+                    frame = this.serializer.Deserialize<Dictionary<string, object>>(line);
+                // TODO: log
+                } catch (ArgumentException) {
+                    return;
+                }
+
+                object type, action;
+                frame.TryGetValue("type", out type);
+                switch(type as string) {
+                    case "update":
+                        this.onUpdate();
+                        break;
+                    case "action":
+                        if(frame.TryGetValue("action", out action) && action is string)
+                            this.onAction((string) action);
+                        break;
+                }
+            }
+        }
     }
 
     [Serializable()]
@@ -404,16 +1254,44 @@ namespace MusicBeePlugin
         private static Regex file_regex = new Regex("^([a-zA-Z]\\:)(\\\\[^\\\\/:*?<>\"|]*(?<![ ]))*(\\.[a-zA-Z]{2,6})$", RegexOptions.Compiled);
 
         public string rootDirectory;
+        // the port of the handler's socket, 0 if only the root directory should be used
+        public int socketPort;
+        // where musicbee downloads podcast episodes to
+        public string podcastFolder;
 
         public static Config def() {
-            return new Config() { rootDirectory = "Z:\\\\tmp\\musicbee-mediakeys\\" };
+            // wine passes the linux environment along, so the handler's default
+            // private runtime directory can be found from here
+            string runtime = Environment.GetEnvironmentVariable("XDG_RUNTIME_DIR");
+            string root = string.IsNullOrEmpty(runtime)
+                ? "Z:\\\\tmp\\musicbee-mediakeys\\"
+                : "Z:\\\\" + runtime.TrimStart('/').Replace('/', '\\') + "\\musicbee-mediakeys\\";
+
+            return new Config() { rootDirectory = root, socketPort = 0, podcastFolder = defaultPodcastFolder() };
+        }
+
+        // musicbee's own default
+        private static string defaultPodcastFolder() {
+            return Path.Combine(Environment.GetFolderPath(Environment.SpecialFolder.MyMusic), "MusicBee", "Podcasts");
+        }
+
+        public bool InPodcastFolder(string file) {
+            if(string.IsNullOrEmpty(this.podcastFolder) || string.IsNullOrEmpty(file))
+                return false;
+
+            string folder = this.podcastFolder.TrimEnd('\\') + "\\";
+            return file.StartsWith(folder, StringComparison.OrdinalIgnoreCase);
         }
 
         // checks if some configuration is valid
         // this may be able to fix some issues if invalid
         // returns true if the configuration is valid (including if it has been fixed), and false if it isn't
         public bool validate() {
-            return validateRoot();
+            return validateRoot() && validatePort();
+        }
+
+        private bool validatePort() {
+            return this.socketPort >= 0 && this.socketPort <= 65535;
         }
 
         private bool validateRoot() {
@@ -441,12 +1319,21 @@ namespace MusicBeePlugin
         // I originally used xml for this, but 
         // the serializer doesn't work with backslashes well
         public string serialize() {
-            return this.rootDirectory;
+            return this.rootDirectory + "\n" + this.socketPort + "\n" + this.podcastFolder;
         }
 
         public static Config deserialize(string val) {
+            string[] lines = val.Split('\n');
+            int port = 0;
+
+            // older configs only have the root directory
+            if(lines.Length > 1)
+                Int32.TryParse(lines[1].Trim(), out port);
+
             return new Config() {
-                rootDirectory = val,
+                rootDirectory = lines[0].TrimEnd('\r'),
+                socketPort = port,
+                podcastFolder = lines.Length > 2 ? lines[2].Trim() : defaultPodcastFolder(),
             };
         }
     }
@@ -456,14 +1343,24 @@ namespace MusicBeePlugin
 
         private Label rootLabel;
         private TextBox rootBox;
+        private Label portLabel;
+        private TextBox portBox;
+        private Label podcastLabel;
+        private TextBox podcastBox;
 
         public ConfigPanel(Config config) {
             this.config = config;
         }
 
         public Config update() {
+            int port;
+            if(!Int32.TryParse(this.portBox.Text.Trim(), out port))
+                return this.config;
+
             Config config = new Config() {
-                rootDirectory = this.rootBox.Text
+                rootDirectory = this.rootBox.Text,
+                socketPort = port,
+                podcastFolder = this.podcastBox.Text.Trim(),
             };
 
             // fallback to old config if it's broken
@@ -489,13 +1386,35 @@ namespace MusicBeePlugin
                 Bounds = new Rectangle(this.rootLabel.Width, 0, 200, this.rootLabel.Height)
             };
 
+            this.portLabel = new Label() {
+                Text = "Socket Port (0 for none): ",
+                AutoSize = true,
+                Location = new Point(0, this.rootLabel.Height + 5)
+            };
+
+            this.portBox = new TextBox() {
+                Bounds = new Rectangle(this.portLabel.Width, this.portLabel.Top, 60, this.portLabel.Height)
+            };
+
+            this.podcastLabel = new Label() {
+                Text = "Podcast Folder: ",
+                AutoSize = true,
+                Location = new Point(0, this.portLabel.Bottom + 5)
+            };
+
+            this.podcastBox = new TextBox() {
+                Bounds = new Rectangle(this.podcastLabel.Width, this.podcastLabel.Top, 200, this.podcastLabel.Height)
+            };
+
             this.updatePanel();
 
-            panel.Controls.AddRange(new Control[] { this.rootLabel, this.rootBox });
+            panel.Controls.AddRange(new Control[] { this.rootLabel, this.rootBox, this.portLabel, this.portBox, this.podcastLabel, this.podcastBox });
         }
 
         public void updatePanel() {
             this.rootBox.Text = this.config.rootDirectory;
+            this.portBox.Text = "" + this.config.socketPort;
+            this.podcastBox.Text = this.config.podcastFolder;
         }
     }
 }